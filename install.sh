@@ -6,6 +6,7 @@ BIN_PATH="/usr/local/bin/$BIN_NAME"
 SERVICE_NAME="thermal-governor"
 SERVICE_PATH="/etc/systemd/system/${SERVICE_NAME}.service"
 STATE_DIR="/var/lib/thermal-governor"
+CONFIG_DIR="/etc/thermal-governor"
 
 # Colors
 RED='\033[0;31m'
@@ -52,6 +53,21 @@ chmod 755 "$BIN_PATH"
 info "Creating state directory: $STATE_DIR"
 mkdir -p "$STATE_DIR"
 
+# Create config directory with the built-in defaults as commented-out reference
+info "Creating config directory: $CONFIG_DIR"
+mkdir -p "$CONFIG_DIR/conf.d"
+if [ ! -f "$CONFIG_DIR/config.toml" ]; then
+    {
+        echo "# thermal-governor configuration — every key is optional."
+        echo "# Uncomment to override a default. Drop-ins in conf.d/*.toml"
+        echo "# override this file in name order."
+        echo ""
+        # A config path that cannot exist yields the pure built-in defaults
+        "$BIN_PATH" print-config --config /dev/null/none 2>/dev/null | sed '/./s/^/# /'
+    } > "$CONFIG_DIR/config.toml"
+    info "Wrote default $CONFIG_DIR/config.toml"
+fi
+
 # Install systemd service
 info "Installing systemd service"
 cat > "$SERVICE_PATH" <<'EOF'
@@ -62,6 +78,9 @@ After=multi-user.target
 [Service]
 Type=simple
 ExecStart=/usr/local/bin/thermal-governor
+ExecReload=/bin/kill -HUP $MAINPID
+# The daemon forces its own exit after 10s; SIGKILL would leave caps applied
+TimeoutStopSec=15
 Restart=always
 RestartSec=5
 StandardOutput=journal
@@ -81,6 +100,7 @@ info "Installation complete!"
 echo ""
 echo "  Service: systemctl status $SERVICE_NAME"
 echo "  Logs:    journalctl -u $SERVICE_NAME -f"
+echo "  Config:  $CONFIG_DIR/config.toml"
 echo "  State:   $STATE_DIR/tuned-params.json"
 echo ""
 
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+// =============================================================================
+// Burst credits: the profile's ceiling for short bursts, paid for in heat
+// =============================================================================
+
+/// Smallest and largest budget the tuner may settle on, °C·s
+pub const BUDGET_MIN: f64 = 10.0;
+pub const BUDGET_MAX: f64 = 600.0;
+
+/// Share of the budget that must be back before another burst, so a
+/// drained bucket doesn't flip between ceiling and table every poll
+const REARM: f64 = 0.5;
+
+/// °C over the package's idle temperature the tuner puts the baseline, so
+/// idle stretches refill the budget
+pub const IDLE_MARGIN: i32 = 3;
+
+/// Token bucket kept with the thermal table, where the tuner learns it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BurstBudget {
+    /// °C above which heat is charged against the budget; below it the
+    /// budget refills at the same rate. Learned from idle temperatures.
+    pub baseline: i32,
+    /// °C·s above the baseline a burst may spend at the ceiling
+    pub budget: f64,
+}
+
+/// Per-run bucket state
+#[derive(Debug, Default)]
+pub struct Credits {
+    /// °C·s left; full until the first poll says otherwise
+    left: Option<f64>,
+    active: bool,
+    /// Fans came on during this burst
+    fans: bool,
+    fan_was_on: bool,
+    /// How deep into the budget (°C·s) the fans came on, since last taken
+    fan_depth: Option<f64>,
+    /// Bursts that ran the budget out with the fans still off
+    quiet_outs: u32,
+}
+
+impl Credits {
+    /// Charge `temp` held for `dt` seconds. Returns whether the profile may
+    /// run at its ceiling until the next poll: credits left and not `hot`.
+    /// A burst only starts on a load `jump`; sitting idle with a full
+    /// bucket is no reason to leave the table.
+    pub fn update(&mut self, b: &BurstBudget, temp: i32, dt: f64, fan_on: bool, hot: bool, jump: bool) -> bool {
+        let left = self.left.get_or_insert(b.budget);
+        *left = (*left - f64::from(temp - b.baseline) * dt).clamp(0.0, b.budget);
+        let depth = b.budget - *left;
+        let fan_started = fan_on && !self.fan_was_on;
+        self.fan_was_on = fan_on;
+
+        if self.active {
+            if fan_started {
+                self.fans = true;
+                self.fan_depth = Some(self.fan_depth.map_or(depth, |d| d.min(depth)));
+            }
+            if *left <= 0.0 || hot {
+                self.active = false;
+                if *left <= 0.0 && !self.fans {
+                    self.quiet_outs += 1;
+                }
+            }
+        } else if jump && *left >= b.budget * REARM && !hot {
+            self.active = true;
+            self.fans = fan_on;
+        }
+        self.active
+    }
+
+    /// °C·s left, `None` before the first poll
+    pub fn left(&self) -> Option<f64> {
+        self.left
+    }
+
+    /// What the tuner learns from since the last call: the shallowest
+    /// depth the fans came on at, and quiet run-outs.
+    pub fn take_outcomes(&mut self) -> (Option<f64>, u32) {
+        (self.fan_depth.take(), std::mem::take(&mut self.quiet_outs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const B: BurstBudget = BurstBudget { baseline: 45, budget: 60.0 };
+
+    #[test]
+    fn full_bucket_waits_for_a_load_jump() {
+        let mut c = Credits::default();
+        assert!(!c.update(&B, 40, 2.0, false, false, false));
+        assert!(c.update(&B, 40, 2.0, false, false, true));
+        assert!(c.update(&B, 50, 2.0, false, false, false), "a burst runs on once started");
+        assert!(!c.update(&B, 50, 2.0, false, true, false));
+    }
+}
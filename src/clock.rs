@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// =============================================================================
+// Clocks: wall time for the daemon, virtual time for the simulator
+// =============================================================================
+
+pub trait Clock: Send {
+    /// Time elapsed since the clock was created
+    fn now(&self) -> Duration;
+    fn sleep(&self, d: Duration);
+    /// Sleep for `d`, or until something arrives on `wake`
+    fn wait(&self, d: Duration, wake: &mpsc::Receiver<()>);
+    /// Seconds since the Unix epoch, for timestamps in recorded data
+    fn unix_time(&self) -> f64;
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, d: Duration) {
+        thread::sleep(d);
+    }
+
+    fn wait(&self, d: Duration, wake: &mpsc::Receiver<()>) {
+        let _ = wake.recv_timeout(d);
+    }
+
+    fn unix_time(&self) -> f64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |d| d.as_secs_f64())
+    }
+}
+
+/// Sleeping just advances the counter. Clones share the same time, so the
+/// simulated plant sees exactly what the governor sees.
+#[derive(Clone)]
+pub struct VirtualClock {
+    nanos: Arc<AtomicU64>,
+    /// Raised once time passes `deadline`, ending the governor loop
+    stop: Arc<AtomicBool>,
+    deadline: Duration,
+}
+
+impl VirtualClock {
+    pub fn new(deadline: Duration, stop: Arc<AtomicBool>) -> Self {
+        Self { nanos: Arc::new(AtomicU64::new(0)), stop, deadline }
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+
+    fn sleep(&self, d: Duration) {
+        let now = self.nanos.fetch_add(d.as_nanos() as u64, Ordering::Relaxed);
+        if Duration::from_nanos(now) + d >= self.deadline {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Nothing runs while virtual time jumps, so nothing can wake it
+    fn wait(&self, d: Duration, _wake: &mpsc::Receiver<()>) {
+        self.sleep(d);
+    }
+
+    /// Virtual runs start at the epoch
+    fn unix_time(&self) -> f64 {
+        self.now().as_secs_f64()
+    }
+}
+
+/// Ends a governor's sleep early: main pokes it to stop, the temperature
+/// sampler when a reading crosses what the governor asked to be woken at.
+pub struct Alarm {
+    tx: mpsc::Sender<()>,
+    rx: mpsc::Receiver<()>,
+}
+
+impl Alarm {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self { tx, rx }
+    }
+
+    pub fn waker(&self) -> mpsc::Sender<()> {
+        self.tx.clone()
+    }
+
+    /// Drop pokes that arrived while awake: whoever sent them has been
+    /// seen to already (a stop also raises the stop flag before poking)
+    pub fn clear(&self) {
+        while self.rx.try_recv().is_ok() {}
+    }
+
+    /// Sleep for `d` on `clock`, or until poked
+    pub fn sleep(&self, clock: &dyn Clock, d: Duration) {
+        clock.wait(d, &self.rx);
+    }
+}
@@ -0,0 +1,641 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::burst::{BUDGET_MAX, BUDGET_MIN};
+use crate::fan::{MAX_LEVEL, WATCHDOG};
+use crate::hw::{CoreType, FreqBounds};
+use crate::{log, Profile, ThermalTable};
+
+// =============================================================================
+// Configuration: /etc/thermal-governor/config.toml + conf.d/*.toml
+// =============================================================================
+
+pub const CONFIG_FILE: &str = "/etc/thermal-governor/config.toml";
+
+/// Everything that used to be a compile-time constant.
+/// Unset keys keep these defaults; drop-ins override the main file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub timing: Timing,
+    pub limits: Limits,
+    pub filter: FilterConfig,
+    pub power_saver: ProfileConfig,
+    pub balanced: ProfileConfig,
+    pub performance: ProfileConfig,
+}
+
+/// Intervals in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Timing {
+    /// Nominal poll; cooldowns and the step-up ramp count in these
+    pub poll_interval: f64,
+    /// Fastest poll, near a threshold or while the temperature moves fast
+    pub min_poll_interval: f64,
+    /// Slowest poll, backed off to while cold and steady
+    pub max_poll_interval: f64,
+    pub tune_interval: f64,
+    pub persist_interval: f64,
+}
+
+/// Frequencies in kHz, as in sysfs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    /// Auto-tune adjustment unit; step-up ramps by twice this per poll
+    pub freq_step: u64,
+    /// Absolute floor for any cap
+    pub min_cap: u64,
+    /// Absolute ceiling for any cap
+    pub max_cap: u64,
+    /// Minimum gap between adjacent table levels
+    pub min_spread: u64,
+}
+
+/// Temperature filter ahead of the controllers; telemetry keeps the raw
+/// readings. The defaults reproduce the unfiltered one-poll difference.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterConfig {
+    pub kind: FilterKind,
+    /// EMA weight of the newest reading, 0–1
+    pub alpha: f64,
+    /// Readings the median is taken over
+    pub samples: usize,
+    /// Kalman: how much the rate of change wanders, °C/s²
+    pub process_noise: f64,
+    /// Kalman: sensor noise, °C
+    pub measurement_noise: f64,
+    /// Nominal polls the rate regression spans; 2 is the plain difference
+    /// between polls
+    pub rate_window: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    None,
+    Ema,
+    Median,
+    Kalman,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// energy_performance_preference written while this profile is active
+    pub epp: String,
+    /// Highest max_cap the auto-tuner may reach
+    pub ceiling: u64,
+    pub controller: ControlMode,
+    /// °C the PID controller holds the package at, and the predictive
+    /// controller's limit
+    pub setpoint: i32,
+    /// Seconds ahead the predictive controller looks
+    pub horizon: f64,
+    /// Starting table, used until auto-tuning has saved its own
+    pub table: ThermalTable,
+    pub power: PowerConfig,
+    pub fan: FanConfig,
+}
+
+/// Control law turning temperature into a cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlMode {
+    /// Threshold levels with hysteresis and cooldown
+    Table,
+    /// Continuous PID toward `setpoint`, gains from the table's `pid`
+    Pid,
+    /// Highest cap the learned thermal model predicts stays under
+    /// `setpoint` for `horizon` seconds; the table until the model is fitted
+    Predictive,
+}
+
+/// What the thermal table's levels are turned into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Actuator {
+    /// `scaling_max_freq` only
+    Frequency,
+    /// RAPL package limits only; frequency left at the limits' max_cap
+    Power,
+    Both,
+}
+
+/// RAPL package limits in watts, highest first and spread evenly from the
+/// table's `max_cap` to its lowest cap. Caps in between get the
+/// interpolated limit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PowerConfig {
+    pub actuator: Actuator,
+    /// Long-term limit (PL1)
+    pub pl1: Vec<f64>,
+    /// Short-term limit (PL2), never below PL1
+    pub pl2: Vec<f64>,
+}
+
+/// Fan level to drop back down only this far below the point that raised it
+const FAN_HYSTERESIS: i32 = 3;
+
+/// Userspace fan curve. Off leaves the fan to firmware.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FanConfig {
+    pub control: bool,
+    /// (°C, level 0–7) points, ascending; below the first the fan is off
+    pub curve: Vec<(i32, u8)>,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            poll_interval: 2.0,
+            min_poll_interval: 0.25,
+            max_poll_interval: 5.0,
+            tune_interval: 120.0,
+            persist_interval: 300.0,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            freq_step: 100_000,  // 100 MHz
+            min_cap: 1_200_000,  // 1.2 GHz absolute floor (reference CPU)
+            max_cap: 4_500_000,  // 4.5 GHz absolute ceiling (reference CPU)
+            min_spread: 200_000, // 200 MHz minimum gap between adjacent levels
+        }
+    }
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            kind: FilterKind::None,
+            alpha: 0.5,
+            samples: 3,
+            process_noise: 0.5,
+            measurement_noise: 1.0,
+            rate_window: 2,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timing: Timing::default(),
+            limits: Limits::default(),
+            filter: FilterConfig::default(),
+            power_saver: ProfileConfig {
+                epp: "power".into(),
+                ceiling: 3_500_000, // 3.5 GHz — no point going higher for fanless
+                controller: ControlMode::Table,
+                setpoint: 55,
+                horizon: 10.0,
+                table: ThermalTable::power_saver(),
+                power: PowerConfig {
+                    actuator: Actuator::Frequency,
+                    pl1: vec![15.0, 12.0, 10.0, 8.0, 6.0],
+                    pl2: vec![25.0, 20.0, 15.0, 10.0, 8.0],
+                },
+                fan: FanConfig {
+                    control: false,
+                    curve: vec![(55, 1), (60, 2), (65, 4), (72, 7)],
+                },
+            },
+            balanced: ProfileConfig {
+                epp: "balance_power".into(),
+                ceiling: 4_500_000,
+                controller: ControlMode::Table,
+                setpoint: 78,
+                horizon: 10.0,
+                table: ThermalTable::balanced(),
+                power: PowerConfig {
+                    actuator: Actuator::Frequency,
+                    pl1: vec![28.0, 22.0, 18.0, 14.0, 10.0],
+                    pl2: vec![50.0, 40.0, 30.0, 20.0, 12.0],
+                },
+                fan: FanConfig {
+                    control: false,
+                    curve: vec![(50, 1), (60, 3), (70, 5), (78, 7)],
+                },
+            },
+            performance: ProfileConfig {
+                epp: "performance".into(),
+                ceiling: 4_500_000,
+                controller: ControlMode::Table,
+                setpoint: 92,
+                horizon: 10.0,
+                table: ThermalTable::performance(),
+                power: PowerConfig {
+                    actuator: Actuator::Frequency,
+                    pl1: vec![45.0, 35.0, 28.0, 20.0, 12.0],
+                    pl2: vec![64.0, 50.0, 40.0, 28.0, 15.0],
+                },
+                fan: FanConfig {
+                    control: false,
+                    curve: vec![(45, 2), (60, 4), (70, 6), (78, 7)],
+                },
+            },
+        }
+    }
+}
+
+impl Timing {
+    pub fn poll(&self) -> Duration {
+        Duration::from_secs_f64(self.poll_interval)
+    }
+
+    pub fn tune(&self) -> Duration {
+        Duration::from_secs_f64(self.tune_interval)
+    }
+
+    pub fn persist(&self) -> Duration {
+        Duration::from_secs_f64(self.persist_interval)
+    }
+}
+
+impl Limits {
+    pub fn clamp(&self, freq: u64) -> u64 {
+        freq.clamp(self.min_cap, self.max_cap)
+    }
+}
+
+impl FanConfig {
+    /// Level for `temp` with the curve shifted by `offset` °C. From
+    /// `current`, the level only drops once `temp` is `FAN_HYSTERESIS`
+    /// below the point that raised it.
+    pub fn level(&self, temp: i32, offset: i32, current: Option<u8>) -> u8 {
+        let at = |t: i32| {
+            self.curve
+                .iter()
+                .take_while(|&&(point, _)| t >= point + offset)
+                .last()
+                .map_or(0, |&(_, level)| level)
+        };
+        let target = at(temp);
+        match current {
+            Some(cur) if target < cur => at(temp + FAN_HYSTERESIS).min(cur),
+            _ => target,
+        }
+    }
+}
+
+impl Config {
+    /// Built-in defaults fitted to the CPU: the limits are its frequency
+    /// range, and the tables, tuned on `FreqBounds::reference`, are
+    /// stretched onto it and snapped to the frequencies it offers.
+    pub fn for_hardware(hw: &FreqBounds) -> Self {
+        let mut cfg = Self::default();
+        let reference = FreqBounds::reference();
+        if *hw == reference {
+            return cfg;
+        }
+        cfg.limits.min_cap = hw.min;
+        cfg.limits.max_cap = hw.max;
+        let limits = cfg.limits.clone();
+        let (low, ref_low) = (hw.table_floor(), reference.table_floor());
+        let scale = (hw.max - low) as f64 / (reference.max - ref_low) as f64;
+        let fit = |f: u64| {
+            let f = low as f64 + f.saturating_sub(ref_low) as f64 * scale;
+            hw.snap(limits.clamp(f as u64 / limits.freq_step * limits.freq_step))
+        };
+        for pc in [&mut cfg.power_saver, &mut cfg.balanced, &mut cfg.performance] {
+            pc.ceiling = fit(pc.ceiling);
+            let t = &mut pc.table;
+            t.max_cap = fit(t.max_cap);
+            for level in &mut t.levels {
+                level.cap = fit(level.cap);
+            }
+            // Snapping may have pulled levels together, and spreading them
+            // again must still land on listed frequencies
+            t.enforce_invariants(pc.ceiling, &limits);
+            let mut prev = t.max_cap + limits.min_spread;
+            for level in &mut t.levels {
+                level.cap = hw.snap(level.cap.min(prev - limits.min_spread)).max(limits.min_cap);
+                prev = level.cap;
+            }
+        }
+        cfg
+    }
+
+    pub fn profile(&self, p: Profile) -> &ProfileConfig {
+        match p {
+            Profile::PowerSaver => &self.power_saver,
+            Profile::Balanced => &self.balanced,
+            Profile::Performance => &self.performance,
+        }
+    }
+
+    /// Load `path` (if present) and then every `conf.d/*.toml` next to it in
+    /// name order, each overriding keys from the ones before and all of
+    /// them overriding the defaults for `hw`.
+    pub fn load(path: &Path, hw: &FreqBounds) -> Result<Self, String> {
+        let mut merged = toml::Value::try_from(Self::for_hardware(hw)).map_err(|e| e.to_string())?;
+
+        let mut files = Vec::new();
+        if path.exists() {
+            files.push(path.to_path_buf());
+        }
+        files.extend(drop_ins(path));
+
+        for file in &files {
+            let text = fs::read_to_string(file).map_err(|e| format!("{}: {e}", file.display()))?;
+            let value: toml::Value = toml::from_str(&text).map_err(|e| format!("{}: {e}", file.display()))?;
+            merge(&mut merged, value);
+        }
+
+        let cfg: Self = merged.try_into().map_err(|e| format!("{}: {e}", describe(&files)))?;
+        cfg.validate().map_err(|e| format!("{}: {e}", describe(&files)))?;
+        if !files.is_empty() {
+            log("config", &format!("Loaded {}", describe(&files)));
+        }
+        Ok(cfg)
+    }
+
+    /// Reject anything `enforce_invariants` would have to change, so the
+    /// running tables are exactly what the file says.
+    pub fn validate(&self) -> Result<(), String> {
+        let t = &self.timing;
+        for (name, v) in [
+            ("poll_interval", t.poll_interval),
+            ("min_poll_interval", t.min_poll_interval),
+            ("max_poll_interval", t.max_poll_interval),
+            ("tune_interval", t.tune_interval),
+            ("persist_interval", t.persist_interval),
+        ] {
+            if !(v.is_finite() && v > 0.0) {
+                return Err(format!("timing.{name} must be > 0, got {v}"));
+            }
+        }
+        if !(t.min_poll_interval <= t.poll_interval && t.poll_interval <= t.max_poll_interval) {
+            return Err(format!(
+                "timing needs min_poll_interval <= poll_interval <= max_poll_interval, got {} / {} / {}",
+                t.min_poll_interval, t.poll_interval, t.max_poll_interval,
+            ));
+        }
+
+        let f = &self.filter;
+        if !(f.alpha > 0.0 && f.alpha <= 1.0) {
+            return Err(format!("filter.alpha must be within (0, 1], got {}", f.alpha));
+        }
+        if f.samples == 0 {
+            return Err("filter.samples must be >= 1".into());
+        }
+        for (name, v) in [("process_noise", f.process_noise), ("measurement_noise", f.measurement_noise)] {
+            if !(v.is_finite() && v > 0.0) {
+                return Err(format!("filter.{name} must be > 0, got {v}"));
+            }
+        }
+        if f.rate_window < 2 {
+            return Err(format!("filter.rate_window must be >= 2, got {}", f.rate_window));
+        }
+
+        let l = &self.limits;
+        if l.freq_step == 0 {
+            return Err("limits.freq_step must be > 0".into());
+        }
+        if l.min_cap >= l.max_cap {
+            return Err(format!("limits.min_cap ({}) must be below max_cap ({})", l.min_cap, l.max_cap));
+        }
+
+        for p in [Profile::PowerSaver, Profile::Balanced, Profile::Performance] {
+            let pc = self.profile(p);
+            let name = p.config_key();
+            if pc.epp.trim().is_empty() {
+                return Err(format!("{name}.epp is empty"));
+            }
+            if !(l.min_cap..=l.max_cap).contains(&pc.ceiling) {
+                return Err(format!("{name}.ceiling ({}) outside limits {}..={}", pc.ceiling, l.min_cap, l.max_cap));
+            }
+            let t = &pc.table;
+            if t.levels.is_empty() {
+                return Err(format!("{name}.table.levels is empty"));
+            }
+            if t.levels.windows(2).any(|w| w[0].above >= w[1].above) {
+                return Err(format!("{name}.table.levels thresholds must be strictly ascending: {}", t.thresholds_str()));
+            }
+            if t.levels.iter().any(|l| l.hysteresis < 0) {
+                return Err(format!("{name}.table.levels hysteresis must be >= 0"));
+            }
+            if !(30..=100).contains(&pc.setpoint) {
+                return Err(format!("{name}.setpoint must be within 30..=100°C, got {}", pc.setpoint));
+            }
+            if !(pc.horizon.is_finite() && (1.0..=600.0).contains(&pc.horizon)) {
+                return Err(format!("{name}.horizon must be within 1..=600 s, got {}", pc.horizon));
+            }
+            let g = &t.pid;
+            if [g.kp, g.ki, g.kd].iter().any(|k| !(k.is_finite() && *k >= 0.0)) || g.kp == 0.0 {
+                return Err(format!("{name}.table.pid gains must be >= 0, kp > 0"));
+            }
+            for (core, ladder) in &t.ladders {
+                if *core == CoreType::Performance {
+                    return Err(format!("{name}.table.ladders.performance: P-cores use max_cap/caps"));
+                }
+                if ladder.is_empty() || ladder.windows(2).any(|w| w[0] < w[1]) || ladder.iter().any(|c| !(l.min_cap..=pc.ceiling).contains(c)) {
+                    return Err(format!(
+                        "{name}.table.ladders.{} must be non-increasing within {}..{} GHz",
+                        core.name(), crate::freq_ghz(l.min_cap), crate::freq_ghz(pc.ceiling),
+                    ));
+                }
+            }
+            if t.sustainable_w.is_some_and(|w| !(w.is_finite() && w > 0.0)) {
+                return Err(format!("{name}.table.sustainable_w must be > 0"));
+            }
+            if let Some(b) = &t.burst {
+                if !(b.budget == 0.0 || (BUDGET_MIN..=BUDGET_MAX).contains(&b.budget)) {
+                    return Err(format!("{name}.table.burst.budget must be 0 (off) or {BUDGET_MIN}..={BUDGET_MAX} °C·s, got {}", b.budget));
+                }
+                if b.baseline >= t.first_threshold() {
+                    return Err(format!("{name}.table.burst.baseline must be below the first threshold ({}°C)", t.first_threshold()));
+                }
+            }
+            let pw = &pc.power;
+            for (key, ladder) in [("pl1", &pw.pl1), ("pl2", &pw.pl2)] {
+                if ladder.is_empty() || ladder.iter().any(|w| !(w.is_finite() && *w > 0.0)) || ladder.windows(2).any(|w| w[0] < w[1]) {
+                    return Err(format!("{name}.power.{key} must be positive and non-increasing"));
+                }
+            }
+            if pw.pl1.len() != pw.pl2.len() || pw.pl1.iter().zip(&pw.pl2).any(|(pl1, pl2)| pl2 < pl1) {
+                return Err(format!("{name}.power.pl2 must have as many points as pl1, each >= pl1"));
+            }
+            let fan = &pc.fan;
+            if fan.curve.iter().any(|&(_, level)| level > MAX_LEVEL)
+                || fan.curve.windows(2).any(|w| w[0].0 >= w[1].0 || w[0].1 > w[1].1)
+            {
+                return Err(format!(
+                    "{name}.fan.curve must have ascending temperatures and non-decreasing levels 0–{MAX_LEVEL}"
+                ));
+            }
+            if fan.control && fan.curve.is_empty() {
+                return Err(format!("{name}.fan.curve is empty"));
+            }
+            // Levels are rewritten every poll to keep the watchdog fed
+            if fan.control && self.timing.max_poll_interval >= WATCHDOG.as_secs_f64() / 2.0 {
+                return Err(format!(
+                    "{name}.fan.control needs timing.max_poll_interval below {}s (fan watchdog)",
+                    WATCHDOG.as_secs() / 2,
+                ));
+            }
+            let mut fixed = t.clone();
+            fixed.enforce_invariants(pc.ceiling, l);
+            if fixed != *t {
+                return Err(format!(
+                    "{name}.table caps {} violate invariants (descending, ≥{} MHz apart, within {}..{} GHz); nearest valid: {}",
+                    t.caps_str(), l.min_spread / 1000,
+                    crate::freq_ghz(l.min_cap), crate::freq_ghz(pc.ceiling), fixed.caps_str(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap_or_default()
+    }
+}
+
+/// `<dir of path>/conf.d/*.toml`, sorted by name
+fn drop_ins(path: &Path) -> Vec<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(".")).join("conf.d");
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|x| x == "toml"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Recursively overlay `over` onto `base`: tables merge key by key, any
+/// other value (including arrays) replaces what was there.
+fn merge(base: &mut toml::Value, over: toml::Value) {
+    match (base, over) {
+        (toml::Value::Table(b), toml::Value::Table(o)) => {
+            for (k, v) in o {
+                match b.get_mut(&k) {
+                    Some(existing) => merge(existing, v),
+                    None => {
+                        b.insert(k, v);
+                    }
+                }
+            }
+        }
+        (b, o) => *b = o,
+    }
+}
+
+fn describe(files: &[PathBuf]) -> String {
+    if files.is_empty() {
+        return "built-in defaults".into();
+    }
+    files.iter().map(|f| f.display().to_string()).collect::<Vec<_>>().join(" + ")
+}
+
+// =============================================================================
+// `print-config` subcommand
+// =============================================================================
+
+/// Print the effective configuration (defaults for this machine + files) as TOML.
+pub fn print(args: &[String]) -> Result<(), String> {
+    let path = match args {
+        [] => PathBuf::from(CONFIG_FILE),
+        [flag, path] if flag == "--config" => PathBuf::from(path),
+        _ => return Err("usage: thermal-governor print-config [--config FILE]".into()),
+    };
+    let cfg = Config::load(&path, &FreqBounds::probe(Path::new("/")).unwrap_or_else(FreqBounds::reference))?;
+    print!("{}", cfg.to_toml());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `config.toml` plus `conf.d/<name>` drop-ins in a scratch dir, loaded
+    /// against the reference CPU
+    fn load(name: &str, main: &str, drop_ins: &[(&str, &str)]) -> Result<Config, String> {
+        let dir = std::env::temp_dir().join(format!("thermal-governor-config-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("conf.d")).unwrap();
+        fs::write(dir.join("config.toml"), main).unwrap();
+        for (file, text) in drop_ins {
+            fs::write(dir.join("conf.d").join(file), text).unwrap();
+        }
+        crate::set_quiet(true);
+        let cfg = Config::load(&dir.join("config.toml"), &FreqBounds::reference());
+        fs::remove_dir_all(&dir).unwrap();
+        cfg
+    }
+
+    #[test]
+    fn drop_ins_override_in_name_order() {
+        let cfg = load(
+            "order",
+            "[balanced]\nsetpoint = 70\nepp = \"balance_power\"\n",
+            &[
+                ("20-late.toml", "[balanced]\nsetpoint = 74\n"),
+                ("10-early.toml", "[balanced]\nsetpoint = 72\n[timing]\npoll_interval = 3.0\n"),
+                ("30-ignored.conf", "[balanced]\nsetpoint = 99\n"),
+            ],
+        )
+        .unwrap();
+        let defaults = Config::default();
+        assert_eq!(cfg.balanced.setpoint, 74);
+        assert_eq!(cfg.balanced.epp, "balance_power");
+        assert_eq!(cfg.timing.poll_interval, 3.0);
+        // Keys nobody set keep their defaults, tables included
+        assert_eq!(cfg.timing.max_poll_interval, defaults.timing.max_poll_interval);
+        assert_eq!(cfg.balanced.table, defaults.balanced.table);
+    }
+
+    #[test]
+    fn unknown_keys_and_bad_ranges_are_rejected() {
+        for (text, needle) in [
+            ("[balanced]\nsetpont = 70\n", "setpont"),
+            ("[timming]\npoll_interval = 2.0\n", "timming"),
+            ("[timing]\nmin_poll_interval = 3.0\n", "min_poll_interval <= poll_interval"),
+            ("[timing]\npoll_interval = -1.0\n", "timing.poll_interval must be > 0"),
+            ("[filter]\nalpha = 1.5\n", "filter.alpha"),
+            ("[limits]\nmin_cap = 5000000\n", "limits.min_cap"),
+            ("[performance]\nceiling = 9000000\n", "performance.ceiling"),
+        ] {
+            let err = load("invalid", text, &[]).unwrap_err();
+            assert!(err.contains(needle), "{text:?}: {err}");
+        }
+        let err = load("invalid-drop-in", "", &[("10-bad.toml", "[power_saver]\nepp = \"\"\n")]).unwrap_err();
+        assert!(err.contains("10-bad.toml") && err.contains("power_saver.epp is empty"), "{err}");
+    }
+
+    #[test]
+    fn defaults_follow_the_hardware() {
+        assert_eq!(Config::for_hardware(&FreqBounds::reference()), Config::default());
+
+        let amd = FreqBounds { min: 1_600_000, max: 5_100_000, base: None, steps: Vec::new() };
+        let cfg = Config::for_hardware(&amd);
+        assert_eq!((cfg.limits.min_cap, cfg.limits.max_cap), (amd.min, amd.max));
+        cfg.validate().unwrap();
+        for pc in [&cfg.power_saver, &cfg.balanced, &cfg.performance] {
+            assert!(pc.table.levels.iter().all(|l| (amd.min..=pc.ceiling).contains(&l.cap)));
+        }
+        assert_eq!(cfg.performance.table.max_cap, amd.max, "the top of the range carries over");
+
+        // Listed frequencies: every level lands on one of them
+        let steps = vec![800_000, 1_400_000, 2_000_000, 2_600_000, 3_000_000];
+        let acpi = FreqBounds { min: 800_000, max: 3_000_000, base: None, steps };
+        let cfg = Config::for_hardware(&acpi);
+        for pc in [&cfg.power_saver, &cfg.balanced, &cfg.performance] {
+            assert!(pc.table.levels.iter().all(|l| acpi.steps.contains(&l.cap)), "{}", pc.table.caps_str());
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use zbus::blocking::proxy::SignalIterator;
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::OwnedValue;
+use zbus::Message;
+
+use crate::Profile;
+
+// =============================================================================
+// power-profiles-daemon over D-Bus
+// =============================================================================
+
+/// (bus name = interface, object path). PPD ≥ 0.20 also exports the UPower name.
+const SERVICES: [(&str, &str); 2] = [
+    ("net.hadess.PowerProfiles", "/net/hadess/PowerProfiles"),
+    ("org.freedesktop.UPower.PowerProfiles", "/org/freedesktop/UPower/PowerProfiles"),
+];
+
+/// Proxy for whichever PPD name answers on the system bus.
+fn connect() -> Result<Proxy<'static>, String> {
+    let conn = Connection::system().map_err(|e| format!("system bus: {e}"))?;
+    let mut last_err = String::new();
+    for (name, path) in SERVICES {
+        match Proxy::new(&conn, name, path, name) {
+            Ok(proxy) => match proxy.get_property::<String>("ActiveProfile") {
+                Ok(_) => return Ok(proxy),
+                Err(e) => last_err = format!("{name}: {e}"),
+            },
+            Err(e) => last_err = format!("{name}: {e}"),
+        }
+    }
+    Err(last_err)
+}
+
+fn read_profile(proxy: &Proxy) -> Result<String, String> {
+    proxy.get_property::<String>("ActiveProfile").map_err(|e| e.to_string())
+}
+
+/// One-shot read of `ActiveProfile` over a fresh connection
+pub fn active_profile() -> Result<String, String> {
+    read_profile(&connect()?)
+}
+
+pub fn detect_profile() -> Option<Profile> {
+    Profile::parse(&active_profile().ok()?)
+}
+
+/// `ActiveProfile` from a `PropertiesChanged(interface, changed, invalidated)` body
+fn changed_profile(msg: &Message) -> Option<String> {
+    let (_, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) = msg.body().deserialize().ok()?;
+    String::try_from(changed.get("ActiveProfile")?.try_clone().ok()?).ok()
+}
+
+/// Subscription to `ActiveProfile` changes on one bus connection
+pub struct Watcher {
+    proxy: Proxy<'static>,
+    signals: SignalIterator<'static>,
+}
+
+impl Watcher {
+    pub fn connect() -> Result<Self, String> {
+        let proxy = connect()?;
+        let props = Proxy::new(
+            proxy.connection(),
+            proxy.destination().to_owned(),
+            proxy.path().to_owned(),
+            "org.freedesktop.DBus.Properties",
+        )
+        .map_err(|e| e.to_string())?;
+        let signals = props
+            .receive_signal_with_args("PropertiesChanged", &[(0, proxy.interface().as_str())])
+            .map_err(|e| e.to_string())?;
+        Ok(Self { proxy, signals })
+    }
+
+    pub fn name(&self) -> &str {
+        self.proxy.interface().as_str()
+    }
+
+    /// Hand the current profile and then every change to `on_change` until
+    /// the connection drops (`Err`) or `on_change` returns false (`Ok`).
+    pub fn run(self, on_change: &mut dyn FnMut(String) -> bool) -> Result<(), String> {
+        // Subscribed first, so nothing is lost between this read and the signals
+        if !on_change(read_profile(&self.proxy)?) {
+            return Ok(());
+        }
+        for msg in self.signals {
+            if let Some(name) = changed_profile(&msg) {
+                if !on_change(name) {
+                    return Ok(());
+                }
+            }
+        }
+        Err("connection closed".into())
+    }
+}
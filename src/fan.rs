@@ -0,0 +1,203 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::log;
+use crate::sensors;
+
+// =============================================================================
+// Fan control: thinkpad_acpi, else a laptop EC's hwmon pwm with an enable knob
+// =============================================================================
+
+const THINKPAD_FAN: &str = "proc/acpi/ibm/fan";
+const HWMON_DIR: &str = "sys/class/hwmon";
+
+/// Top of the level scale (thinkpad_acpi's); pwm is scaled to 0–255
+pub const MAX_LEVEL: u8 = 7;
+
+/// hwmon drivers whose pwm is the CPU fan. GPU (amdgpu, nouveau) and
+/// chassis header (nct6775, it87) pwms may drive anything, so never guess.
+const PWM_DRIVERS: &[&str] = &["thinkpad", "dell_smm", "asus", "applesmc"];
+
+/// Without a level write for this long the fan goes back to firmware.
+/// Levels are written every poll, so the poll interval must stay well below.
+pub const WATCHDOG: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+enum Device {
+    /// `/proc/acpi/ibm/fan` (needs `fan_control=1`); the firmware runs the
+    /// watchdog itself
+    ThinkPad(PathBuf),
+    /// `pwmN` + `pwmN_enable`; our own watchdog thread hands it back
+    Pwm { pwm: PathBuf, enable: PathBuf, desc: String },
+}
+
+impl Device {
+    fn discover(root: &Path) -> Option<Self> {
+        let tp = root.join(THINKPAD_FAN);
+        if let Ok(status) = fs::read_to_string(&tp) {
+            // The command list only appears when fan_control=1
+            if status.lines().any(|l| l.starts_with("commands:") && l.contains("level")) {
+                return Some(Self::ThinkPad(tp));
+            }
+            log("fan", "thinkpad_acpi loaded without fan_control=1, trying hwmon pwm");
+        }
+        sensors::numbered_entries(&root.join(HWMON_DIR), "hwmon").into_iter().find_map(|h| {
+            let name = fs::read_to_string(h.join("name")).map_or("?".into(), |s| s.trim().to_string());
+            let pwm = (1..=8).find_map(|n| {
+                let pwm = h.join(format!("pwm{n}"));
+                let enable = h.join(format!("pwm{n}_enable"));
+                (pwm.exists() && enable.exists()).then(|| Self::Pwm { pwm, enable, desc: format!("{name}/pwm{n}") })
+            })?;
+            if !PWM_DRIVERS.contains(&name.as_str()) {
+                log("fan", &format!("Ignoring {} (not a known CPU fan driver)", pwm.desc()));
+                return None;
+            }
+            Some(pwm)
+        })
+    }
+
+    fn desc(&self) -> String {
+        match self {
+            Self::ThinkPad(path) => format!("thinkpad_acpi ({})", path.display()),
+            Self::Pwm { pwm, desc, .. } => format!("{desc} ({})", pwm.display()),
+        }
+    }
+}
+
+/// Writes fan levels and makes sure the fan never stays under manual
+/// control without us: hands back on request, and on a stalled caller.
+pub struct FanControl {
+    device: Device,
+    /// Level last written, `None` while firmware is in charge
+    level: Option<u8>,
+    /// Last level write, watched by the pwm watchdog
+    heartbeat: Arc<Mutex<Instant>>,
+    /// Set while the pwm is under manual control
+    manual: Arc<AtomicBool>,
+    watchdog_started: bool,
+    dry_run: bool,
+}
+
+impl FanControl {
+    pub fn discover(root: &Path, dry_run: bool) -> Option<Self> {
+        let device = Device::discover(root)?;
+        log("fan", &format!("Controllable fan: {}", device.desc()));
+        Some(Self {
+            device,
+            level: None,
+            heartbeat: Arc::new(Mutex::new(Instant::now())),
+            manual: Arc::new(AtomicBool::new(false)),
+            watchdog_started: false,
+            dry_run,
+        })
+    }
+
+    /// Files `restore` writes back, with their current values
+    pub fn knobs(&self) -> Vec<(PathBuf, String)> {
+        let read = |p: &Path| fs::read_to_string(p).ok().map(|s| s.trim().to_string());
+        match &self.device {
+            Device::ThinkPad(path) => {
+                // Reads as a status report; the restorable part is the level
+                let level = read(path).and_then(|s| {
+                    s.lines().find_map(|l| Some(l.strip_prefix("level:")?.trim().to_string()))
+                });
+                level.map(|l| (path.clone(), format!("level {l}"))).into_iter().collect()
+            }
+            Device::Pwm { pwm, enable, .. } => [pwm, enable]
+                .into_iter()
+                .filter_map(|p| Some((p.clone(), read(p)?)))
+                .collect(),
+        }
+    }
+
+    /// Set `level` (0..=MAX_LEVEL), or `None` to hand the fan to firmware.
+    /// Every call counts as a heartbeat for the watchdog.
+    pub fn set_level(&mut self, level: Option<u8>) {
+        let Some(level) = level.map(|l| l.min(MAX_LEVEL)) else {
+            if self.level.take().is_some() {
+                self.hand_back();
+            }
+            return;
+        };
+        if self.dry_run {
+            if self.level != Some(level) {
+                log("fan", &format!("[dry-run] level {level}"));
+            }
+            self.level = Some(level);
+            return;
+        }
+        *self.heartbeat.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+        match &self.device {
+            Device::ThinkPad(path) => {
+                if self.level.is_none() {
+                    let _ = fs::write(path, format!("watchdog {}", WATCHDOG.as_secs()));
+                }
+                // Rewritten every time: any command re-arms the firmware watchdog
+                let _ = fs::write(path, format!("level {level}"));
+            }
+            Device::Pwm { pwm, enable, .. } => {
+                // Manual mode again after a hand-back or a watchdog trip
+                let retake = !self.manual.swap(true, Ordering::Relaxed);
+                if retake {
+                    let _ = fs::write(enable, "1");
+                }
+                if retake || self.level != Some(level) {
+                    let duty = u32::from(level) * 255 / u32::from(MAX_LEVEL);
+                    let _ = fs::write(pwm, duty.to_string());
+                }
+                self.start_watchdog();
+            }
+        }
+        self.level = Some(level);
+    }
+
+    fn hand_back(&mut self) {
+        if self.dry_run {
+            log("fan", "[dry-run] back to firmware control");
+            return;
+        }
+        match &self.device {
+            Device::ThinkPad(path) => {
+                let _ = fs::write(path, "level auto");
+                let _ = fs::write(path, "watchdog 0");
+            }
+            Device::Pwm { enable, .. } => {
+                self.manual.store(false, Ordering::Relaxed);
+                let _ = fs::write(enable, "2");
+            }
+        }
+        log("fan", "Back to firmware control");
+    }
+
+    /// Once per instance: if the caller stops writing levels, give the pwm
+    /// back to firmware (`pwm_enable=2`) until it writes again. The thread
+    /// ends when this is dropped, so a replacement is never undercut.
+    fn start_watchdog(&mut self) {
+        let Device::Pwm { enable, .. } = &self.device else { return };
+        if self.watchdog_started {
+            return;
+        }
+        self.watchdog_started = true;
+        let (enable, heartbeat, manual) = (enable.clone(), Arc::downgrade(&self.heartbeat), Arc::downgrade(&self.manual));
+        let spawned = thread::Builder::new().name("fan-watchdog".into()).spawn(move || watchdog(&enable, &heartbeat, &manual));
+        if let Err(e) = spawned {
+            log("fan", &format!("Cannot start watchdog ({e})"));
+        }
+    }
+}
+
+fn watchdog(enable: &Path, heartbeat: &Weak<Mutex<Instant>>, manual: &Weak<AtomicBool>) {
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        let (Some(heartbeat), Some(manual)) = (heartbeat.upgrade(), manual.upgrade()) else { return };
+        let stale = heartbeat.lock().unwrap_or_else(|e| e.into_inner()).elapsed() >= WATCHDOG;
+        if stale && manual.swap(false, Ordering::Relaxed) {
+            let _ = fs::write(enable, "2");
+            log("fan", &format!("Watchdog: no update for {}s, back to firmware control", WATCHDOG.as_secs()));
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::VecDeque;
+
+use crate::config::{FilterConfig, FilterKind};
+
+// =============================================================================
+// Sensor filtering: smoothed temperature + rate of change over a window
+// =============================================================================
+
+/// What the control laws see instead of the raw reading
+#[derive(Debug, Clone, Copy)]
+pub struct Filtered {
+    pub temp: f64,
+    /// °C/s, least-squares slope over the last `rate_window` polls' worth
+    /// of filtered values
+    pub rate: f64,
+}
+
+pub struct SensorFilter {
+    cfg: FilterConfig,
+    /// Seconds of history the rate regression spans
+    span: f64,
+    /// Raw readings for the median
+    raw: VecDeque<f64>,
+    /// (seconds, filtered °C) for the rate regression
+    history: VecDeque<(f64, f64)>,
+    /// EMA value, or the Kalman state [°C, °C/s]
+    state: Option<[f64; 2]>,
+    /// Kalman covariance
+    cov: [[f64; 2]; 2],
+    last_at: f64,
+}
+
+impl SensorFilter {
+    /// `poll` is the nominal poll interval, which `rate_window` counts in
+    pub fn new(cfg: &FilterConfig, poll: f64) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            span: (cfg.rate_window - 1) as f64 * poll,
+            raw: VecDeque::new(),
+            history: VecDeque::new(),
+            state: None,
+            cov: [[0.0; 2]; 2],
+            last_at: 0.0,
+        }
+    }
+
+    /// Start over, e.g. after the sensor was unreadable for a while
+    pub fn reset(&mut self) {
+        self.raw.clear();
+        self.history.clear();
+        self.state = None;
+    }
+
+    /// Feed a raw reading taken at `at` seconds (any fixed origin).
+    pub fn push(&mut self, raw: i32, at: f64) -> Filtered {
+        let x = f64::from(raw);
+        let dt = at - self.last_at;
+        self.last_at = at;
+        let temp = match self.cfg.kind {
+            FilterKind::None => x,
+            FilterKind::Ema => {
+                let y = self.state.map_or(x, |[y, _]| y + self.cfg.alpha * (x - y));
+                self.state = Some([y, 0.0]);
+                y
+            }
+            FilterKind::Median => {
+                self.raw.push_back(x);
+                while self.raw.len() > self.cfg.samples {
+                    self.raw.pop_front();
+                }
+                let mut sorted: Vec<f64> = self.raw.iter().copied().collect();
+                sorted.sort_by(f64::total_cmp);
+                let mid = sorted.len() / 2;
+                if sorted.len().is_multiple_of(2) { (sorted[mid - 1] + sorted[mid]) / 2.0 } else { sorted[mid] }
+            }
+            FilterKind::Kalman => self.kalman(x, dt),
+        };
+
+        // By time, so faster polls average more readings rather than
+        // differencing one-degree steps over a fraction of a second
+        self.history.push_back((at, temp));
+        while self.history.len() > 2 && self.history.get(1).is_some_and(|&(t, _)| at - t >= self.span - 1e-6) {
+            self.history.pop_front();
+        }
+        Filtered { temp, rate: slope(&self.history) }
+    }
+
+    /// Constant-velocity Kalman filter: the temperature drifts at a rate
+    /// that itself wanders by `process_noise` °C/s², read through a sensor
+    /// with `measurement_noise` °C of noise.
+    fn kalman(&mut self, x: f64, dt: f64) -> f64 {
+        let Some([t, v]) = self.state else {
+            let r = self.cfg.measurement_noise.powi(2);
+            self.state = Some([x, 0.0]);
+            self.cov = [[r, 0.0], [0.0, 1.0]];
+            return x;
+        };
+        let dt = dt.max(0.0);
+        let q = self.cfg.process_noise.powi(2);
+        let r = self.cfg.measurement_noise.powi(2);
+
+        // Predict
+        let (t, v) = (t + v * dt, v);
+        let [[p00, p01], [p10, p11]] = self.cov;
+        let p00 = p00 + dt * (p10 + p01) + dt * dt * p11 + q * dt.powi(3) / 3.0;
+        let p01 = p01 + dt * p11 + q * dt * dt / 2.0;
+        let p10 = p10 + dt * p11 + q * dt * dt / 2.0;
+        let p11 = p11 + q * dt;
+
+        // Update with the reading
+        let s = p00 + r;
+        let (k0, k1) = (p00 / s, p10 / s);
+        let innovation = x - t;
+        self.state = Some([t + k0 * innovation, v + k1 * innovation]);
+        self.cov = [[(1.0 - k0) * p00, (1.0 - k0) * p01], [p10 - k1 * p00, p11 - k1 * p01]];
+        t + k0 * innovation
+    }
+}
+
+/// Least-squares slope of (x, y) points, 0 with fewer than two
+fn slope(points: &VecDeque<(f64, f64)>) -> f64 {
+    let n = points.len() as f64;
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let (mx, my) = points.iter().fold((0.0, 0.0), |(a, b), &(x, y)| (a + x / n, b + y / n));
+    let (sxy, sxx) = points
+        .iter()
+        .fold((0.0, 0.0), |(sxy, sxx), &(x, y)| (sxy + (x - mx) * (y - my), sxx + (x - mx).powi(2)));
+    if sxx > 0.0 { sxy / sxx } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(kind: FilterKind, rate_window: usize) -> SensorFilter {
+        SensorFilter::new(&FilterConfig { kind, rate_window, ..FilterConfig::default() }, 2.0)
+    }
+
+    /// Filtered temperatures for readings one poll (2 s) apart
+    fn run(f: &mut SensorFilter, readings: &[i32]) -> Vec<Filtered> {
+        readings.iter().enumerate().map(|(i, &r)| f.push(r, i as f64 * 2.0)).collect()
+    }
+
+    #[test]
+    fn slope_of_known_points() {
+        let pts = |p: &[(f64, f64)]| p.iter().copied().collect::<VecDeque<_>>();
+        assert_eq!(slope(&pts(&[])), 0.0);
+        assert_eq!(slope(&pts(&[(0.0, 50.0)])), 0.0);
+        assert_eq!(slope(&pts(&[(0.0, 40.0), (1.0, 42.0), (2.0, 44.0)])), 2.0);
+        assert_eq!(slope(&pts(&[(0.0, 1.0), (1.0, 0.0), (2.0, 3.0), (3.0, 2.0)])), 0.6);
+        assert_eq!(slope(&pts(&[(5.0, 40.0), (5.0, 60.0)])), 0.0, "no spread in time");
+    }
+
+    #[test]
+    fn unfiltered_rate_is_the_difference_between_polls() {
+        let out = run(&mut filter(FilterKind::None, 2), &[50, 54, 52]);
+        let got: Vec<_> = out.iter().map(|f| (f.temp, f.rate)).collect();
+        assert_eq!(got, [(50.0, 0.0), (54.0, 2.0), (52.0, -1.0)]);
+    }
+
+    #[test]
+    fn rate_window_spans_time_not_readings() {
+        // Four polls' worth (6 s) at 1 °C/s, read every half second
+        let mut f = filter(FilterKind::None, 4);
+        let out: Vec<_> = (0..20).map(|i| f.push(40 + i / 2, f64::from(i) * 0.5)).collect();
+        let last = out.last().unwrap();
+        assert!((last.rate - 1.0).abs() < 0.1, "rate {}", last.rate);
+        assert!(f.history.len() <= 14, "{} points kept", f.history.len());
+    }
+
+    #[test]
+    fn ema_weighs_the_newest_reading_by_alpha() {
+        let out = run(&mut filter(FilterKind::Ema, 2), &[40, 50, 50]);
+        let temps: Vec<_> = out.iter().map(|f| f.temp).collect();
+        assert_eq!(temps, [40.0, 45.0, 47.5]);
+    }
+
+    #[test]
+    fn median_rejects_a_one_poll_spike() {
+        let out = run(&mut filter(FilterKind::Median, 2), &[50, 50, 90, 51, 51]);
+        let temps: Vec<_> = out.iter().map(|f| f.temp).collect();
+        assert_eq!(temps, [50.0, 50.0, 50.0, 51.0, 51.0]);
+        assert!(out.iter().all(|f| f.rate.abs() <= 0.5), "the spike never shows as a rate");
+    }
+
+    #[test]
+    fn kalman_tracks_a_ramp_and_damps_a_spike() {
+        // 0.5 °C/s for a minute
+        let ramp: Vec<i32> = (0..30).map(|i| 50 + i).collect();
+        let out = run(&mut filter(FilterKind::Kalman, 2), &ramp);
+        let last = out.last().unwrap();
+        assert!((last.temp - 79.0).abs() < 1.0, "temp {}", last.temp);
+        assert!((last.rate - 0.5).abs() < 0.1, "rate {}", last.rate);
+
+        // Told the sensor is noisier than the temperature moves
+        let cfg = FilterConfig { kind: FilterKind::Kalman, process_noise: 0.05, measurement_noise: 2.0, ..FilterConfig::default() };
+        let mut steady = vec![50; 20];
+        steady.push(60);
+        let out = run(&mut SensorFilter::new(&cfg, 2.0), &steady);
+        assert!(out.last().unwrap().temp < 55.0, "spike passed as {}", out.last().unwrap().temp);
+    }
+}
@@ -0,0 +1,1200 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::{Alarm, Clock};
+use crate::fan::FanControl;
+use crate::log;
+use crate::sensors::{self, Sensors};
+
+// =============================================================================
+// Hardware backend
+// =============================================================================
+
+/// Everything the governor reads from or writes to the machine.
+pub trait HardwareBackend: Send {
+    /// CPU package temperature in °C, `None` if no sensor is readable.
+    fn cpu_temp(&mut self) -> Option<i32>;
+    /// Highest fan RPM across all fans.
+    fn fan_rpm(&mut self) -> u32;
+    /// Whether there is anything to actuate at all.
+    fn has_cpufreq(&self) -> bool;
+    /// Core types present, P-cores (or the only kind) first.
+    fn core_types(&self) -> Vec<CoreType>;
+    /// Write `scaling_max_freq` on every policy of one core type.
+    fn set_max_freq(&mut self, core: CoreType, freq: u64);
+    /// Write `scaling_min_freq` + EPP on every CPU and the HWP dynamic boost flag.
+    fn apply_base(&mut self, min_freq: u64, epp: &str, boost: u8);
+    /// Package energy counter in µJ (RAPL), `None` if unavailable.
+    fn energy_uj(&mut self) -> Option<u64>;
+    /// Whether package power limits can be written.
+    fn has_power_limits(&self) -> bool;
+    /// Write PL1/PL2 in µW; `None` puts back the limits found at startup.
+    fn set_power_limits(&mut self, limits: Option<(u64, u64)>);
+    /// Busy fraction (0–1) of all CPUs since the previous call, `None` on
+    /// the first call or if unknown.
+    fn utilization(&mut self) -> Option<f64>;
+    /// Total µs some task waited for a CPU since boot (PSI), `None` if the
+    /// kernel doesn't account pressure.
+    fn cpu_stall_us(&mut self) -> Option<u64>;
+    /// Kernel thermal throttle counters since boot, `None` if not exposed.
+    fn throttle_count(&mut self) -> Option<ThrottleCount>;
+    /// What a sampler much faster than the poll saw since the previous
+    /// call, `None` without one or if it read nothing.
+    fn temp_span(&mut self) -> Option<TempSpan>;
+    /// Sleep up to `d` on `alarm`, ending early once a sampled temperature
+    /// rises past `wake_above`. Paces the sampler to `d` meanwhile.
+    fn wait(&mut self, clock: &dyn Clock, alarm: &Alarm, d: Duration, wake_above: Option<i32>);
+    /// Whether the fan speed can be set.
+    fn has_fan_control(&self) -> bool;
+    /// Fan level 0–7; `None` hands the fan back to firmware. Feeds the fan
+    /// watchdog, so it is called every poll while in control.
+    fn set_fan_level(&mut self, level: Option<u8>);
+    /// Current value of every knob the writers above touch.
+    fn snapshot(&self) -> Snapshot;
+    /// Put back exactly what `snapshot` saw.
+    fn restore(&mut self, snap: &Snapshot);
+}
+
+/// Kind of core behind a cpufreq policy. Anything that isn't a hybrid
+/// Intel part is all `Performance`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoreType {
+    /// `cpu_core`: P-cores, throttled first
+    Performance,
+    /// `cpu_atom`: E-cores
+    Efficient,
+    /// `cpu_atom` with a lower `cpuinfo_max_freq` than the other E-cores
+    /// (Meteor Lake's SoC-tile LP E-cores)
+    LowPower,
+}
+
+impl CoreType {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Performance => "performance",
+            Self::Efficient => "efficient",
+            Self::LowPower => "low_power",
+        }
+    }
+}
+
+/// Cumulative `thermal_throttle` event counts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThrottleCount {
+    /// `package_throttle_count`, once per package
+    pub package: u64,
+    /// `core_throttle_count`, summed over physical cores
+    pub core: u64,
+}
+
+impl ThrottleCount {
+    /// Events between `earlier` and now
+    pub fn since(self, earlier: Self) -> Self {
+        Self {
+            package: self.package.saturating_sub(earlier.package),
+            core: self.core.saturating_sub(earlier.core),
+        }
+    }
+
+    pub fn total(self) -> u64 {
+        self.package + self.core
+    }
+}
+
+/// Temperatures between two polls
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempSpan {
+    pub max: i32,
+    pub min: i32,
+    pub mean: f64,
+    pub samples: u32,
+}
+
+/// Gathers samples until the next poll takes them
+#[derive(Debug, Default)]
+pub struct SpanAcc {
+    max: i32,
+    min: i32,
+    sum: i64,
+    samples: u32,
+}
+
+impl SpanAcc {
+    pub fn add(&mut self, temp: i32) {
+        if self.samples == 0 {
+            (self.max, self.min) = (temp, temp);
+        }
+        self.max = self.max.max(temp);
+        self.min = self.min.min(temp);
+        self.sum += i64::from(temp);
+        self.samples += 1;
+    }
+
+    pub fn take(&mut self) -> Option<TempSpan> {
+        let acc = std::mem::take(self);
+        (acc.samples > 0).then(|| TempSpan {
+            max: acc.max,
+            min: acc.min,
+            mean: acc.sum as f64 / f64::from(acc.samples),
+            samples: acc.samples,
+        })
+    }
+}
+
+// =============================================================================
+// Snapshot of the original settings
+// =============================================================================
+
+/// Saved while the daemon runs; still present at startup means we crashed
+pub const SNAPSHOT_FILE: &str = "/var/lib/thermal-governor/original-settings.json";
+
+/// Raw knob values keyed by sysfs path, written back verbatim
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub knobs: BTreeMap<String, String>,
+}
+
+impl Snapshot {
+    /// Write every value back to its file. Needs no backend, so the panic
+    /// hook can use it too. Returns the number of knobs that failed.
+    pub fn write_back(&self) -> usize {
+        // The kernel rejects min > max, so restoring min may only succeed
+        // once max has been restored, or the other way round: retry once.
+        let failed: Vec<_> = self.knobs.iter().filter(|(p, v)| fs::write(p, v).is_err()).collect();
+        failed.into_iter().filter(|(p, v)| fs::write(p, v).is_err()).count()
+    }
+
+    fn load(path: &Path) -> Option<Self> {
+        serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+    }
+
+    fn save(&self, path: &Path) {
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let saved = serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            log("hw", &format!("Cannot save {}: {e}", path.display()));
+        }
+    }
+}
+
+/// Snapshot the settings to restore on exit. With `path`, the snapshot is
+/// also kept on disk until `discard_snapshot`, and one left behind by a
+/// crashed run is restored first so its caps are not taken as original.
+pub fn take_snapshot(hw: &mut dyn HardwareBackend, path: Option<&Path>) -> Snapshot {
+    if let Some(stale) = path.and_then(Snapshot::load) {
+        log("hw", &format!("Previous run did not exit cleanly, restoring {} saved settings", stale.knobs.len()));
+        hw.restore(&stale);
+    }
+    let snap = hw.snapshot();
+    if let Some(path) = path {
+        snap.save(path);
+    }
+    snap
+}
+
+/// Called after a clean restore
+pub fn discard_snapshot(path: Option<&Path>) {
+    if let Some(path) = path {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// `restore-settings`: write back a snapshot left behind by a run that did
+/// not exit cleanly, for the uninstaller. Nothing to do if there is none.
+pub fn restore_saved(args: &[String]) -> Result<(), String> {
+    let path = match args {
+        [] => PathBuf::from(SNAPSHOT_FILE),
+        [flag, path] if flag == "--snapshot" => PathBuf::from(path),
+        _ => return Err("usage: thermal-governor restore-settings [--snapshot FILE]".into()),
+    };
+    if !path.exists() {
+        return Ok(());
+    }
+    let snap = Snapshot::load(&path).ok_or_else(|| format!("Cannot parse {}", path.display()))?;
+    match snap.write_back() {
+        0 => {
+            discard_snapshot(Some(&path));
+            println!("Restored {} saved settings", snap.knobs.len());
+            Ok(())
+        }
+        n => Err(format!("Restore failed for {n} of {} settings, {} kept", snap.knobs.len(), path.display())),
+    }
+}
+
+// =============================================================================
+// Sysfs backend (real hardware, or a fake tree under another root)
+// =============================================================================
+
+const CPU_DIR: &str = "sys/devices/system/cpu";
+const HWP_BOOST: &str = "sys/devices/system/cpu/intel_pstate/hwp_dynamic_boost";
+const CPUFREQ_BOOST: &str = "sys/devices/system/cpu/cpufreq/boost";
+const POLICY_DIR: &str = "sys/devices/system/cpu/cpufreq";
+const ATOM_CPUS: &str = "sys/devices/cpu_atom/cpus";
+const CORE_CPUS: &str = "sys/devices/cpu_core/cpus";
+const RAPL_PACKAGE: &str = "sys/class/powercap/intel-rapl:0";
+const PROC_STAT: &str = "proc/stat";
+const PROC_PRESSURE: &str = "proc/pressure/cpu";
+
+/// Temperature reads between polls. A sysfs read is a few microseconds,
+/// but each one wakes the CPU, so the sampler slows as the poll backs off.
+const SAMPLE_MIN: Duration = Duration::from_millis(100);
+const SAMPLE_MAX: Duration = Duration::from_secs(1);
+
+/// Sampler period for polls `poll` apart
+pub fn sample_period(poll: Duration) -> Duration {
+    (poll / 8).clamp(SAMPLE_MIN, SAMPLE_MAX)
+}
+
+/// What the sampler thread reads, how often, and whom it wakes
+struct Watch {
+    /// The sensor the governor reads (it may be rediscovered)
+    path: Option<PathBuf>,
+    period: Duration,
+    /// Poke `waker` when a reading rises past this
+    wake_above: Option<i32>,
+    waker: Option<mpsc::Sender<()>>,
+}
+
+/// The watch shared with the sampler thread, and what it gathered
+struct Sampler {
+    watch: Arc<Mutex<Watch>>,
+    span: Arc<Mutex<SpanAcc>>,
+    started: bool,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        let watch = Watch { path: None, period: SAMPLE_MIN, wake_above: None, waker: None };
+        Self { watch: Arc::new(Mutex::new(watch)), span: Arc::default(), started: false }
+    }
+}
+
+impl Sampler {
+    fn watch(&self) -> std::sync::MutexGuard<'_, Watch> {
+        self.watch.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn track(&mut self, path: Option<&Path>) {
+        let mut watch = self.watch();
+        if watch.path.as_deref() != path {
+            watch.path = path.map(Path::to_path_buf);
+        }
+    }
+
+    /// Start on first use; the thread ends when the backend is dropped
+    fn take(&mut self) -> Option<TempSpan> {
+        if !self.started {
+            self.started = true;
+            let (watch, span) = (Arc::downgrade(&self.watch), Arc::downgrade(&self.span));
+            let spawned = thread::Builder::new().name("temp-sampler".into()).spawn(move || sample(&watch, &span));
+            if let Err(e) = spawned {
+                log("hw", &format!("Cannot start temperature sampler ({e}), using poll readings only"));
+            }
+            return None;
+        }
+        self.span.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+fn sample(watch: &Weak<Mutex<Watch>>, span: &Weak<Mutex<SpanAcc>>) {
+    let mut last: Option<i32> = None;
+    while let (Some(watch), Some(span)) = (watch.upgrade(), span.upgrade()) {
+        let w = watch.lock().unwrap_or_else(|e| e.into_inner());
+        let period = w.period;
+        if let Some(t) = w.path.as_deref().and_then(sensors::read_temp) {
+            span.lock().unwrap_or_else(|e| e.into_inner()).add(t);
+            // Only on the way up: a reading that stays above woke it already
+            if let (Some(above), Some(waker)) = (w.wake_above, &w.waker) {
+                if t > above && last.is_some_and(|l| l <= above) {
+                    let _ = waker.send(());
+                }
+            }
+            last = Some(t);
+        }
+        drop(w);
+        drop((watch, span));
+        thread::sleep(period);
+    }
+}
+
+/// Which cpufreq driver owns the CPUs, from `scaling_driver`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpufreqDriver {
+    /// intel_pstate (active) or intel_cpufreq (passive)
+    Intel,
+    /// amd-pstate, amd-pstate-epp
+    Amd,
+    /// acpi-cpufreq and friends: no EPP, boost via cpufreq/boost
+    Other(String),
+}
+
+impl CpufreqDriver {
+    fn detect(root: &Path, policies: &[Policy]) -> Self {
+        let name = policies
+            .iter()
+            .find_map(|d| fs::read_to_string(d.dir.join("scaling_driver")).ok())
+            .map(|s| s.trim().to_string());
+        match name.as_deref() {
+            Some("intel_pstate" | "intel_cpufreq") => Self::Intel,
+            Some(n) if n.starts_with("amd-pstate") => Self::Amd,
+            Some(n) => Self::Other(n.to_string()),
+            // Older kernels and fake trees: go by which driver dir exists
+            None if root.join(CPU_DIR).join("intel_pstate").is_dir() => Self::Intel,
+            None if root.join(CPU_DIR).join("amd_pstate").is_dir() => Self::Amd,
+            None => Self::Other("unknown".into()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Intel => "intel_pstate",
+            Self::Amd => "amd-pstate",
+            Self::Other(n) => n,
+        }
+    }
+
+    fn boost_path(&self, root: &Path) -> PathBuf {
+        match self {
+            Self::Intel => root.join(HWP_BOOST),
+            Self::Amd | Self::Other(_) => root.join(CPUFREQ_BOOST),
+        }
+    }
+}
+
+/// One cpufreq policy and the range its driver accepts
+#[derive(Debug, Clone)]
+struct Policy {
+    dir: PathBuf,
+    cpus: Vec<u32>,
+    core: CoreType,
+    /// Lowest useful max cap: `amd_pstate_lowest_nonlinear_freq` if present
+    /// (below it voltage stops dropping), else `cpuinfo_min_freq`
+    floor: Option<u64>,
+    /// `amd_pstate_max_freq` if present, else `cpuinfo_max_freq`
+    ceiling: Option<u64>,
+    /// Hardware minimum, for `scaling_min_freq`
+    min: Option<u64>,
+    /// `base_frequency`: guaranteed non-turbo frequency (intel_pstate)
+    base: Option<u64>,
+    /// `scaling_available_frequencies` ascending, empty if any value goes
+    steps: Vec<u64>,
+    has_epp: bool,
+}
+
+impl Policy {
+    fn probe(dir: PathBuf, cpus: Vec<u32>) -> Self {
+        let read = |f: &str| fs::read_to_string(dir.join(f)).ok()?.trim().parse::<u64>().ok();
+        let min = read("cpuinfo_min_freq");
+        let mut steps: Vec<u64> = fs::read_to_string(dir.join("scaling_available_frequencies"))
+            .map(|s| s.split_whitespace().filter_map(|f| f.parse().ok()).collect())
+            .unwrap_or_default();
+        steps.sort_unstable();
+        steps.dedup();
+        Self {
+            floor: read("amd_pstate_lowest_nonlinear_freq").or(min),
+            ceiling: read("amd_pstate_max_freq").or_else(|| read("cpuinfo_max_freq")),
+            min,
+            base: read("base_frequency"),
+            steps,
+            has_epp: dir.join("energy_performance_preference").exists(),
+            cpus,
+            core: CoreType::Performance,
+            dir,
+        }
+    }
+
+    fn clamp_max(&self, freq: u64) -> u64 {
+        let freq = self.ceiling.map_or(freq, |c| freq.min(c));
+        snap(&self.steps, self.floor.map_or(freq, |f| freq.max(f)))
+    }
+
+    fn clamp_min(&self, freq: u64) -> u64 {
+        let freq = self.min.map_or(freq, |m| freq.max(m));
+        self.ceiling.map_or(freq, |c| freq.min(c))
+    }
+}
+
+/// `cpufreq/policyN` with their `related_cpus`, or on trees without those,
+/// one policy per `cpuN/cpufreq`. Core types come from the hybrid PMU lists.
+fn discover_policies(root: &Path) -> Vec<Policy> {
+    let mut found: Vec<(PathBuf, Vec<u32>)> = fs::read_dir(root.join(POLICY_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().starts_with("policy"))
+        .map(|e| {
+            let cpus = fs::read_to_string(e.path().join("related_cpus")).map_or(Vec::new(), |s| parse_cpus(&s));
+            (e.path(), cpus)
+        })
+        .collect();
+    if found.is_empty() {
+        found = cpufreq_dirs(&root.join(CPU_DIR))
+            .into_iter()
+            .map(|d| {
+                let n = d.parent().and_then(|c| c.file_name()?.to_str()?.strip_prefix("cpu")?.parse().ok());
+                (d, n.into_iter().collect())
+            })
+            .collect();
+    }
+    found.sort();
+
+    let mut policies: Vec<Policy> = found.into_iter().map(|(d, cpus)| Policy::probe(d, cpus)).collect();
+
+    // Hybrid only if the kernel exposes both PMUs
+    let read_cpus = |p: &str| fs::read_to_string(root.join(p)).ok().map(|s| parse_cpus(&s));
+    if let (Some(atom), Some(_)) = (read_cpus(ATOM_CPUS), read_cpus(CORE_CPUS)) {
+        for p in &mut policies {
+            if p.cpus.iter().any(|c| atom.contains(c)) {
+                p.core = CoreType::Efficient;
+            }
+        }
+        let fastest_atom = policies.iter().filter(|p| p.core == CoreType::Efficient).filter_map(|p| p.ceiling).max();
+        for p in &mut policies {
+            if p.core == CoreType::Efficient && p.ceiling < fastest_atom {
+                p.core = CoreType::LowPower;
+            }
+        }
+    }
+    policies
+}
+
+/// Highest of `steps` (ascending) not above `freq`, the lowest if none is;
+/// `freq` itself when there are no steps.
+fn snap(steps: &[u64], freq: u64) -> u64 {
+    match steps.iter().rev().find(|&&s| s <= freq) {
+        Some(&s) => s,
+        None => steps.first().copied().unwrap_or(freq),
+    }
+}
+
+/// Frequency range of the P-cores (all cores on non-hybrid parts), kHz
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreqBounds {
+    /// Policy floor: `cpuinfo_min_freq`, or AMD's lowest nonlinear
+    pub min: u64,
+    pub max: u64,
+    /// `base_frequency` where the driver tells, a hint for scaling tables
+    pub base: Option<u64>,
+    /// `scaling_available_frequencies`, ascending; empty if any value goes
+    pub steps: Vec<u64>,
+}
+
+impl FreqBounds {
+    /// The Core Ultra 7 155H the default tables were tuned on
+    pub fn reference() -> Self {
+        Self { min: 400_000, max: 4_500_000, base: Some(1_400_000), steps: Vec::new() }
+    }
+
+    /// Where tables bottom out. The reference tables' 1.2 GHz is its base
+    /// clock less a seventh: the same share of base elsewhere, else the floor.
+    pub fn table_floor(&self) -> u64 {
+        self.base.map_or(self.min, |b| (b / 7 * 6).clamp(self.min, self.max))
+    }
+
+    pub fn snap(&self, freq: u64) -> u64 {
+        snap(&self.steps, freq)
+    }
+
+    /// Read from the P-core policies under `root`; `None` without cpufreq
+    /// or with a range too odd to fit tables into.
+    pub fn probe(root: &Path) -> Option<Self> {
+        let policies = discover_policies(root);
+        let p_cores: Vec<&Policy> = policies.iter().filter(|p| p.core == CoreType::Performance).collect();
+        let max = p_cores.iter().filter_map(|p| p.ceiling).max()?;
+        let floor = p_cores.iter().filter_map(|p| p.floor).min()?;
+        let base = p_cores.iter().filter_map(|p| p.base).min();
+        let mut steps: Vec<u64> = p_cores.iter().flat_map(|p| p.steps.iter().copied()).collect();
+        steps.sort_unstable();
+        steps.dedup();
+        (floor < max).then_some(Self { min: floor, max, base, steps })
+    }
+
+    pub fn describe(&self) -> String {
+        let steps = if self.steps.is_empty() { String::new() } else { format!(", {} steps", self.steps.len()) };
+        let base = self.base.map_or(String::new(), |b| format!(", base {} GHz", crate::freq_ghz(b)));
+        format!("{}–{} GHz{base}{steps}", crate::freq_ghz(self.min), crate::freq_ghz(self.max))
+    }
+}
+
+/// Kernel cpulist format: "0-5,8,10-11"
+fn parse_cpus(list: &str) -> Vec<u32> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        let (lo, hi) = part.split_once('-').unwrap_or((part, part));
+        if let (Ok(lo), Ok(hi)) = (lo.parse::<u32>(), hi.parse::<u32>()) {
+            cpus.extend(lo..=hi);
+        }
+    }
+    cpus
+}
+
+/// Package power domain of the powercap interface
+#[derive(Debug, Clone)]
+struct Rapl {
+    energy: PathBuf,
+    /// `constraint_N_power_limit_uw` named long_term / short_term
+    pl1: PathBuf,
+    pl2: Option<PathBuf>,
+    /// Limits at startup, µW
+    original: (u64, u64),
+}
+
+impl Rapl {
+    fn probe(root: &Path) -> Option<Self> {
+        let dir = root.join(RAPL_PACKAGE);
+        let constraint = |name: &str| {
+            (0..4).find_map(|n| {
+                let found = fs::read_to_string(dir.join(format!("constraint_{n}_name"))).ok()?;
+                (found.trim() == name).then(|| dir.join(format!("constraint_{n}_power_limit_uw")))
+            })
+        };
+        let read = |p: &Path| fs::read_to_string(p).ok()?.trim().parse::<u64>().ok();
+        let pl1 = constraint("long_term")?;
+        let pl2 = constraint("short_term");
+        let original_pl1 = read(&pl1)?;
+        let original = (original_pl1, pl2.as_deref().and_then(read).unwrap_or(original_pl1));
+        Some(Self { energy: dir.join("energy_uj"), pl1, pl2, original })
+    }
+}
+
+/// (busy, total) jiffies from the aggregate `cpu` line of /proc/stat;
+/// idle and iowait count as not busy
+fn cpu_jiffies(stat: &str) -> Option<(u64, u64)> {
+    let fields: Vec<u64> = stat
+        .lines()
+        .find(|l| l.starts_with("cpu "))?
+        .split_whitespace()
+        .skip(1)
+        .map_while(|f| f.parse().ok())
+        .collect();
+    if fields.len() < 4 {
+        return None;
+    }
+    let total: u64 = fields.iter().take(8).sum(); // guest time is already in user
+    let idle = fields[3] + fields.get(4).copied().unwrap_or(0);
+    Some((total - idle, total))
+}
+
+/// `total=` of the `some` line of /proc/pressure/cpu, in µs
+fn stall_total(pressure: &str) -> Option<u64> {
+    pressure
+        .lines()
+        .find(|l| l.starts_with("some "))?
+        .split_whitespace()
+        .find_map(|f| f.strip_prefix("total="))?
+        .parse()
+        .ok()
+}
+
+pub struct SysfsBackend {
+    sensors: Sensors,
+    policies: Vec<Policy>,
+    /// `cpuN/thermal_throttle` dirs, one per core, with its package id
+    throttle: Vec<(PathBuf, u32)>,
+    rapl: Option<Rapl>,
+    fan: Option<FanControl>,
+    stat: PathBuf,
+    /// (busy, total) jiffies at the last `utilization` call
+    last_stat: Option<(u64, u64)>,
+    pressure: PathBuf,
+    sampler: Sampler,
+    driver: CpufreqDriver,
+    boost: PathBuf,
+    /// EPP values the driver offers, empty if it doesn't say
+    epp_choices: Vec<String>,
+    /// Unsupported EPP already reported
+    epp_warned: Option<String>,
+    /// Read sensors but never write (log intended writes instead)
+    dry_run: bool,
+}
+
+impl SysfsBackend {
+    pub fn new(root: &Path, dry_run: bool) -> Self {
+        let policies = discover_policies(root);
+        if policies.is_empty() {
+            log("hw", &format!("No cpufreq policies under {}", root.display()));
+        }
+        let driver = CpufreqDriver::detect(root, &policies);
+        let boost = driver.boost_path(root);
+        let epp_choices: Vec<String> = policies
+            .first()
+            .and_then(|p| fs::read_to_string(p.dir.join("energy_performance_available_preferences")).ok())
+            .map(|s| s.split_whitespace().map(String::from).collect())
+            .unwrap_or_default();
+
+        let bounds = match policies.first() {
+            Some(&Policy { floor: Some(lo), ceiling: Some(hi), .. }) => {
+                format!("{}–{} GHz", crate::freq_ghz(lo), crate::freq_ghz(hi))
+            }
+            _ => "unknown".into(),
+        };
+        let epp = if policies.iter().any(|p| p.has_epp) { "yes" } else { "no" };
+        log("hw", &format!(
+            "Driver: {}, {} policies, max cap range {bounds}, EPP: {epp}, boost: {}",
+            driver.name(),
+            policies.len(),
+            if boost.exists() { boost.display().to_string() } else { "none".into() },
+        ));
+
+        let rapl = Rapl::probe(root);
+        if let Some(r) = &rapl {
+            log("hw", &format!(
+                "RAPL package limits: PL1 {:.1} W, PL2 {:.1} W",
+                r.original.0 as f64 / 1e6, r.original.1 as f64 / 1e6,
+            ));
+        }
+
+        let throttle = throttle_dirs(&root.join(CPU_DIR));
+        if throttle.is_empty() {
+            log("hw", "No thermal_throttle counters, firmware throttling will go unnoticed");
+        }
+
+        let mut backend = Self {
+            sensors: Sensors::discover(root),
+            policies,
+            throttle,
+            rapl,
+            fan: FanControl::discover(root, dry_run),
+            stat: root.join(PROC_STAT),
+            last_stat: None,
+            pressure: root.join(PROC_PRESSURE),
+            sampler: Sampler::default(),
+            driver,
+            boost,
+            epp_choices,
+            epp_warned: None,
+            dry_run,
+        };
+        let cores = backend.core_types();
+        if cores.len() > 1 {
+            let groups: Vec<String> = cores
+                .iter()
+                .map(|&core| {
+                    let group: Vec<&Policy> = backend.policies.iter().filter(|p| p.core == core).collect();
+                    let cpus: usize = group.iter().map(|p| p.cpus.len()).sum();
+                    let max = group.iter().filter_map(|p| p.ceiling).max().unwrap_or(0);
+                    format!("{} ×{cpus} (≤{} GHz)", core.name(), crate::freq_ghz(max))
+                })
+                .collect();
+            log("hw", &format!("Hybrid CPU: {}", groups.join(", ")));
+        }
+        backend.policies.sort_by_key(|p| p.core);
+        backend
+    }
+
+    fn write(&self, path: &Path, val: &str) {
+        if !self.dry_run {
+            let _ = fs::write(path, val);
+        }
+    }
+
+    /// Configured EPP strings are intel_pstate's; amd-pstate-epp accepts the
+    /// same set, but say so once if the driver doesn't offer this one.
+    fn check_epp(&mut self, epp: &str) {
+        if self.epp_choices.is_empty() || self.epp_choices.iter().any(|c| c == epp) {
+            return;
+        }
+        if self.epp_warned.as_deref() != Some(epp) {
+            log("hw", &format!(
+                "EPP '{epp}' not offered by {} (available: {})",
+                self.driver.name(), self.epp_choices.join(" "),
+            ));
+            self.epp_warned = Some(epp.to_string());
+        }
+    }
+}
+
+impl HardwareBackend for SysfsBackend {
+    fn cpu_temp(&mut self) -> Option<i32> {
+        let temp = self.sensors.cpu_temp();
+        self.sampler.track(self.sensors.temp_path());
+        temp
+    }
+
+    fn fan_rpm(&mut self) -> u32 {
+        self.sensors.fan_rpm()
+    }
+
+    fn has_cpufreq(&self) -> bool {
+        !self.policies.is_empty()
+    }
+
+    fn core_types(&self) -> Vec<CoreType> {
+        let mut cores: Vec<CoreType> = self.policies.iter().map(|p| p.core).collect();
+        cores.sort();
+        cores.dedup();
+        cores
+    }
+
+    fn set_max_freq(&mut self, core: CoreType, freq: u64) {
+        for p in self.policies.iter().filter(|p| p.core == core) {
+            self.write(&p.dir.join("scaling_max_freq"), &p.clamp_max(freq).to_string());
+        }
+    }
+
+    fn apply_base(&mut self, min_freq: u64, epp: &str, boost: u8) {
+        if self.dry_run {
+            log("hw", &format!("[dry-run] min={min_freq} EPP={epp} boost={boost}"));
+        }
+        self.check_epp(epp);
+        for p in &self.policies {
+            self.write(&p.dir.join("scaling_min_freq"), &p.clamp_min(min_freq).to_string());
+            if p.has_epp {
+                self.write(&p.dir.join("energy_performance_preference"), epp);
+            }
+        }
+        if self.boost.exists() {
+            self.write(&self.boost, &boost.to_string());
+        }
+    }
+
+    fn energy_uj(&mut self) -> Option<u64> {
+        let rapl = self.rapl.as_ref()?;
+        fs::read_to_string(&rapl.energy).ok()?.trim().parse().ok()
+    }
+
+    fn has_power_limits(&self) -> bool {
+        self.rapl.is_some()
+    }
+
+    fn set_power_limits(&mut self, limits: Option<(u64, u64)>) {
+        let Some(rapl) = &self.rapl else { return };
+        let (pl1, pl2) = limits.unwrap_or(rapl.original);
+        self.write(&rapl.pl1, &pl1.to_string());
+        if let Some(path) = &rapl.pl2 {
+            self.write(path, &pl2.to_string());
+        }
+    }
+
+    fn utilization(&mut self) -> Option<f64> {
+        let now = cpu_jiffies(&fs::read_to_string(&self.stat).ok()?)?;
+        let (busy0, total0) = self.last_stat.replace(now)?;
+        let total = now.1.checked_sub(total0).filter(|&t| t > 0)?;
+        Some(now.0.saturating_sub(busy0) as f64 / total as f64)
+    }
+
+    fn cpu_stall_us(&mut self) -> Option<u64> {
+        stall_total(&fs::read_to_string(&self.pressure).ok()?)
+    }
+
+    fn throttle_count(&mut self) -> Option<ThrottleCount> {
+        if self.throttle.is_empty() {
+            return None;
+        }
+        let read = |p: PathBuf| fs::read_to_string(p).ok()?.trim().parse::<u64>().ok();
+        let mut packages = BTreeMap::new();
+        let mut count = ThrottleCount::default();
+        for (dir, pkg) in &self.throttle {
+            count.core += read(dir.join("core_throttle_count")).unwrap_or(0);
+            if let Some(n) = read(dir.join("package_throttle_count")) {
+                packages.insert(*pkg, n);
+            }
+        }
+        count.package = packages.values().sum();
+        Some(count)
+    }
+
+    fn temp_span(&mut self) -> Option<TempSpan> {
+        self.sampler.take()
+    }
+
+    fn wait(&mut self, clock: &dyn Clock, alarm: &Alarm, d: Duration, wake_above: Option<i32>) {
+        {
+            let mut watch = self.sampler.watch();
+            watch.period = sample_period(d);
+            watch.wake_above = wake_above;
+            // Each governor run sleeps on its own alarm
+            watch.waker = Some(alarm.waker());
+        }
+        alarm.sleep(clock, d);
+    }
+
+    fn has_fan_control(&self) -> bool {
+        self.fan.is_some()
+    }
+
+    fn set_fan_level(&mut self, level: Option<u8>) {
+        if let Some(fan) = &mut self.fan {
+            fan.set_level(level);
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let mut files: Vec<PathBuf> = self
+            .policies
+            .iter()
+            .flat_map(|p| {
+                ["scaling_min_freq", "scaling_max_freq", "energy_performance_preference"].map(|f| p.dir.join(f))
+            })
+            .collect();
+        files.push(self.boost.clone());
+        if let Some(rapl) = &self.rapl {
+            files.push(rapl.pl1.clone());
+            files.extend(rapl.pl2.clone());
+        }
+        let mut knobs: BTreeMap<String, String> = files
+            .into_iter()
+            .filter_map(|p| {
+                let val = fs::read_to_string(&p).ok()?;
+                Some((p.to_string_lossy().into_owned(), val.trim().to_string()))
+            })
+            .collect();
+        if let Some(fan) = &self.fan {
+            knobs.extend(fan.knobs().into_iter().map(|(p, v)| (p.to_string_lossy().into_owned(), v)));
+        }
+        Snapshot { knobs }
+    }
+
+    fn restore(&mut self, snap: &Snapshot) {
+        if self.dry_run {
+            log("hw", &format!("[dry-run] would restore {} settings", snap.knobs.len()));
+            return;
+        }
+        match snap.write_back() {
+            0 => log("hw", &format!("Restored {} original settings", snap.knobs.len())),
+            n => log("hw", &format!("Restore failed for {n} of {} settings", snap.knobs.len())),
+        }
+    }
+}
+
+fn cpufreq_dirs(cpu_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(entries) = fs::read_dir(cpu_dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let s = name.to_string_lossy();
+            if s.starts_with("cpu")
+                && s.len() > 3
+                && s.as_bytes()[3].is_ascii_digit()
+            {
+                let p = entry.path().join("cpufreq");
+                if p.is_dir() {
+                    dirs.push(p);
+                }
+            }
+        }
+    }
+    dirs.sort();
+    dirs
+}
+
+/// `cpuN/thermal_throttle` (Intel only) with `topology/physical_package_id`,
+/// one per physical core: SMT siblings report the same core counter
+fn throttle_dirs(cpu_dir: &Path) -> Vec<(PathBuf, u32)> {
+    let topology = |cpu: &Path, name: &str| -> u32 {
+        fs::read_to_string(cpu.join("topology").join(name)).ok().and_then(|s| s.trim().parse().ok()).unwrap_or(0)
+    };
+    let mut dirs: Vec<(u32, PathBuf, u32, u32)> = fs::read_dir(cpu_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| {
+            let n = e.file_name().to_str()?.strip_prefix("cpu")?.parse::<u32>().ok()?;
+            let dir = e.path().join("thermal_throttle");
+            let (pkg, core) = (topology(&e.path(), "physical_package_id"), topology(&e.path(), "core_id"));
+            dir.is_dir().then_some((n, dir, pkg, core))
+        })
+        .collect();
+    dirs.sort();
+    let mut seen = BTreeSet::new();
+    dirs.into_iter().filter(|&(_, _, pkg, core)| seen.insert((pkg, core))).map(|(_, dir, pkg, _)| (dir, pkg)).collect()
+}
+
+// =============================================================================
+// In-memory fake
+// =============================================================================
+
+/// Sensor values are set by the caller; writes land in plain fields.
+#[derive(Debug, Clone)]
+pub struct FakeBackend {
+    pub temp: Option<i32>,
+    pub fan_rpm: u32,
+    pub util: Option<f64>,
+    /// Kernel throttle counters, `None` as if not exposed
+    pub throttle: Option<ThrottleCount>,
+    pub max_freq: u64,
+    pub min_freq: u64,
+    pub epp: String,
+    pub boost: u8,
+    /// PL1/PL2 in µW, `None` when at the original limits
+    pub power_limits: Option<(u64, u64)>,
+    /// `None` while firmware runs the fan
+    pub fan_level: Option<u8>,
+    /// Number of `scaling_max_freq` writes so far
+    pub freq_writes: u32,
+}
+
+impl FakeBackend {
+    pub fn new(temp: i32) -> Self {
+        Self {
+            temp: Some(temp),
+            fan_rpm: 0,
+            util: None,
+            throttle: None,
+            max_freq: 0,
+            min_freq: 0,
+            epp: String::new(),
+            boost: 0,
+            power_limits: None,
+            fan_level: None,
+            freq_writes: 0,
+        }
+    }
+}
+
+impl HardwareBackend for FakeBackend {
+    fn cpu_temp(&mut self) -> Option<i32> {
+        self.temp
+    }
+
+    fn fan_rpm(&mut self) -> u32 {
+        self.fan_rpm
+    }
+
+    fn has_cpufreq(&self) -> bool {
+        true
+    }
+
+    fn core_types(&self) -> Vec<CoreType> {
+        vec![CoreType::Performance]
+    }
+
+    fn set_max_freq(&mut self, _core: CoreType, freq: u64) {
+        self.max_freq = freq;
+        self.freq_writes += 1;
+    }
+
+    fn apply_base(&mut self, min_freq: u64, epp: &str, boost: u8) {
+        self.min_freq = min_freq;
+        self.epp = epp.to_string();
+        self.boost = boost;
+    }
+
+    fn energy_uj(&mut self) -> Option<u64> {
+        None
+    }
+
+    fn has_power_limits(&self) -> bool {
+        true
+    }
+
+    fn set_power_limits(&mut self, limits: Option<(u64, u64)>) {
+        self.power_limits = limits;
+    }
+
+    fn utilization(&mut self) -> Option<f64> {
+        self.util
+    }
+
+    fn cpu_stall_us(&mut self) -> Option<u64> {
+        None
+    }
+
+    fn throttle_count(&mut self) -> Option<ThrottleCount> {
+        self.throttle
+    }
+
+    fn temp_span(&mut self) -> Option<TempSpan> {
+        None
+    }
+
+    fn wait(&mut self, clock: &dyn Clock, alarm: &Alarm, d: Duration, _wake_above: Option<i32>) {
+        alarm.sleep(clock, d);
+    }
+
+    fn has_fan_control(&self) -> bool {
+        true
+    }
+
+    fn set_fan_level(&mut self, level: Option<u8>) {
+        self.fan_level = level;
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let knobs = [
+            ("scaling_max_freq", self.max_freq.to_string()),
+            ("scaling_min_freq", self.min_freq.to_string()),
+            ("energy_performance_preference", self.epp.clone()),
+            ("hwp_dynamic_boost", self.boost.to_string()),
+            ("fan", self.fan_level.map_or("auto".into(), |l| l.to_string())),
+        ];
+        Snapshot { knobs: knobs.into_iter().map(|(k, v)| (k.to_string(), v)).collect() }
+    }
+
+    fn restore(&mut self, snap: &Snapshot) {
+        let get = |k: &str| snap.knobs.get(k).cloned().unwrap_or_default();
+        self.max_freq = get("scaling_max_freq").parse().unwrap_or(self.max_freq);
+        self.min_freq = get("scaling_min_freq").parse().unwrap_or(self.min_freq);
+        self.epp = get("energy_performance_preference");
+        self.boost = get("hwp_dynamic_boost").parse().unwrap_or(self.boost);
+        self.fan_level = get("fan").parse().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch sysfs root, unique to the test
+    fn fake_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("thermal-governor-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        root
+    }
+
+    /// `cpufreq/policyN` for CPU N under `root`, with the given files
+    fn policy(root: &Path, n: u32, files: &[(&str, &str)]) -> PathBuf {
+        let dir = root.join(POLICY_DIR).join(format!("policy{n}"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("related_cpus"), format!("{n}\n")).unwrap();
+        for (name, value) in files {
+            fs::write(dir.join(name), format!("{value}\n")).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn driver_and_cap_range_come_from_the_policy() {
+        let intel = [("cpuinfo_min_freq", "400000"), ("cpuinfo_max_freq", "4800000")];
+        let amd = [
+            ("cpuinfo_min_freq", "400000"),
+            ("cpuinfo_max_freq", "5500000"),
+            ("amd_pstate_lowest_nonlinear_freq", "1400000"),
+            ("amd_pstate_max_freq", "5100000"),
+        ];
+        let acpi = [
+            ("cpuinfo_min_freq", "1400000"),
+            ("cpuinfo_max_freq", "2200000"),
+            ("scaling_available_frequencies", "2200000 1800000 1400000"),
+        ];
+        // driver, policy files, expected driver, boost knob, clamp_max of (low, mid, high)
+        let cases = [
+            ("intel_pstate", &intel[..], CpufreqDriver::Intel, HWP_BOOST, [400_000, 2_000_000, 4_800_000]),
+            ("intel_cpufreq", &intel[..], CpufreqDriver::Intel, HWP_BOOST, [400_000, 2_000_000, 4_800_000]),
+            ("amd-pstate-epp", &amd[..], CpufreqDriver::Amd, CPUFREQ_BOOST, [1_400_000, 2_000_000, 5_100_000]),
+            ("amd-pstate", &amd[..], CpufreqDriver::Amd, CPUFREQ_BOOST, [1_400_000, 2_000_000, 5_100_000]),
+            ("acpi-cpufreq", &acpi[..], CpufreqDriver::Other("acpi-cpufreq".into()), CPUFREQ_BOOST, [1_400_000, 1_800_000, 2_200_000]),
+        ];
+        for (name, files, driver, boost, clamped) in cases {
+            let root = fake_root("driver");
+            let mut files = files.to_vec();
+            files.push(("scaling_driver", name));
+            policy(&root, 0, &files);
+            let policies = discover_policies(&root);
+            let detected = CpufreqDriver::detect(&root, &policies);
+            fs::remove_dir_all(&root).unwrap();
+            assert_eq!(detected, driver, "{name}");
+            assert_eq!(detected.boost_path(&root), root.join(boost), "{name}");
+            let got = [100_000, 2_000_000, 9_000_000].map(|f| policies[0].clamp_max(f));
+            assert_eq!(got, clamped, "{name}");
+        }
+
+        // Without scaling_driver, the driver's sysfs dir decides
+        let root = fake_root("driver-dir");
+        policy(&root, 0, &intel);
+        fs::create_dir_all(root.join(CPU_DIR).join("intel_pstate")).unwrap();
+        let detected = CpufreqDriver::detect(&root, &discover_policies(&root));
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(detected, CpufreqDriver::Intel);
+    }
+
+    #[test]
+    fn base_frequency_places_the_tables_not_the_floor() {
+        let root = fake_root("bounds");
+        let files = [("cpuinfo_min_freq", "400000"), ("cpuinfo_max_freq", "4800000"), ("base_frequency", "1400000")];
+        policy(&root, 0, &files);
+        let bounds = FreqBounds::probe(&root);
+        fs::remove_dir_all(&root).unwrap();
+        let bounds = bounds.unwrap();
+        assert_eq!((bounds.min, bounds.max, bounds.base), (400_000, 4_800_000, Some(1_400_000)));
+        assert_eq!(bounds.table_floor(), 1_200_000);
+        let cfg = crate::config::Config::for_hardware(&bounds);
+        assert_eq!(cfg.limits.min_cap, 400_000);
+        assert_eq!(cfg.power_saver.table.lowest_cap(), 1_200_000, "power-saver's bottom stays reachable");
+    }
+
+    #[test]
+    fn smt_siblings_share_one_throttle_counter() {
+        let root = std::env::temp_dir().join(format!("thermal-governor-throttle-{}", std::process::id()));
+        // cpu0/cpu2 and cpu1/cpu3 are hyperthreads of cores 0 and 1
+        for (cpu, core) in [(0, 0), (1, 1), (2, 0), (3, 1)] {
+            let dir = root.join(format!("cpu{cpu}"));
+            fs::create_dir_all(dir.join("thermal_throttle")).unwrap();
+            fs::create_dir_all(dir.join("topology")).unwrap();
+            fs::write(dir.join("topology/physical_package_id"), "0\n").unwrap();
+            fs::write(dir.join("topology/core_id"), format!("{core}\n")).unwrap();
+        }
+        let dirs = throttle_dirs(&root);
+        fs::remove_dir_all(&root).unwrap();
+        let cpus: Vec<_> = dirs.iter().map(|(d, _)| d.parent().unwrap().file_name().unwrap().to_owned()).collect();
+        assert_eq!(cpus, ["cpu0", "cpu1"]);
+    }
+
+    #[test]
+    fn sampler_wakes_the_governor_on_the_way_up_only() {
+        use crate::clock::SystemClock;
+        use std::time::Instant;
+        let file = std::env::temp_dir().join(format!("thermal-governor-sampler-{}", std::process::id()));
+        fs::write(&file, "50000\n").unwrap();
+        let (clock, alarm) = (SystemClock::new(), Alarm::new());
+        let mut sampler = Sampler::default();
+        sampler.track(Some(&file));
+        {
+            let mut watch = sampler.watch();
+            watch.wake_above = Some(60);
+            watch.waker = Some(alarm.waker());
+        }
+        sampler.take();
+        thread::sleep(3 * SAMPLE_MIN);
+        fs::write(&file, "70000\n").unwrap();
+        let start = Instant::now();
+        alarm.sleep(&clock, Duration::from_secs(5));
+        assert!(start.elapsed() < Duration::from_secs(1), "a rise past the mark wakes it");
+        // Staying above is no news
+        let start = Instant::now();
+        alarm.sleep(&clock, 5 * SAMPLE_MIN);
+        fs::remove_file(&file).unwrap();
+        assert!(start.elapsed() >= 5 * SAMPLE_MIN);
+    }
+
+    #[test]
+    fn proc_stat_counts_idle_and_iowait_as_not_busy() {
+        for (stat, want) in [
+            ("cpu  100 0 50 800 50 0 0 0 0 0\ncpu0 50 0 25 400 25 0 0 0 0 0\n", Some((150, 1000))),
+            ("cpu  10 0 10 70 10 0 0 0 5 5\n", Some((20, 100))), // guest already in user
+            ("cpu  10 0 10 80\n", Some((20, 100))),
+            ("cpu  10 0 10 80 junk\n", Some((20, 100))),
+            ("cpu  10 0 10\n", None),
+            ("cpu  10 0 x 80 10\n", None),
+            ("cpu0 10 0 10 80 10\n", None),
+            ("", None),
+        ] {
+            assert_eq!(cpu_jiffies(stat), want, "{stat:?}");
+        }
+    }
+
+    #[test]
+    fn pressure_total_comes_from_the_some_line() {
+        for (pressure, want) in [
+            ("some avg10=0.00 avg60=0.00 avg300=0.00 total=12345\nfull avg10=0.00 avg60=0.00 avg300=0.00 total=99\n", Some(12345)),
+            ("full avg10=0.00 total=5\nsome avg10=0.00 total=7\n", Some(7)),
+            ("full avg10=0.00 avg60=0.00 avg300=0.00 total=99\n", None),
+            ("some avg10=1.00 avg60=0.50 avg300=0.10\n", None),
+            ("some avg10=1.00 total=lots\n", None),
+            ("", None),
+        ] {
+            assert_eq!(stall_total(pressure), want, "{pressure:?}");
+        }
+    }
+}
@@ -1,7 +1,8 @@
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
@@ -10,24 +11,34 @@ use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 
+mod burst;
+mod clock;
+mod config;
+mod dbus;
+mod fan;
+mod filter;
+mod hw;
+mod model;
+mod profile_source;
+mod replay;
+mod sensors;
+mod shutdown;
+mod sim;
+mod telemetry;
+
+use burst::{BurstBudget, Credits, BUDGET_MAX, BUDGET_MIN, IDLE_MARGIN};
+use clock::{Alarm, Clock, SystemClock};
+use config::{Actuator, Config, ControlMode, Limits, PowerConfig, ProfileConfig, Timing, CONFIG_FILE};
+use filter::SensorFilter;
+use hw::{CoreType, FakeBackend, FreqBounds, HardwareBackend, SysfsBackend, ThrottleCount, SNAPSHOT_FILE};
+use model::{FitWindow, ThermalModel};
+use shutdown::Shutdown;
+use telemetry::{Record, Telemetry, TELEMETRY_FILE};
+
 // =============================================================================
-// Hardware paths (ThinkPad X1, Intel Core Ultra 7 155H)
+// Paths (tunables live in config.rs, hardware access in hw.rs)
 // =============================================================================
 
-const TEMP_SENSOR: &str = "/sys/class/thermal/thermal_zone8/temp"; // x86_pkg_temp
-const FAN1_SENSOR: &str = "/sys/class/hwmon/hwmon7/fan1_input";
-const FAN2_SENSOR: &str = "/sys/class/hwmon/hwmon7/fan2_input";
-const HWP_BOOST_PATH: &str = "/sys/devices/system/cpu/intel_pstate/hwp_dynamic_boost";
-
-const POLL_INTERVAL: Duration = Duration::from_secs(2);
-const TUNE_INTERVAL: Duration = Duration::from_secs(120);
-const PERSIST_INTERVAL: Duration = Duration::from_secs(300);
-
-const FREQ_STEP: u64 = 100_000; // 100 MHz
-const MIN_CAP: u64 = 1_200_000; // 1.2 GHz absolute floor
-const MAX_CAP: u64 = 4_500_000; // 4.5 GHz absolute ceiling
-const MIN_SPREAD: u64 = 200_000; // 200 MHz minimum gap between adjacent levels
-
 const STATE_FILE: &str = "/var/lib/thermal-governor/tuned-params.json";
 
 // =============================================================================
@@ -59,174 +70,417 @@ impl Profile {
         }
     }
 
-    fn epp(self) -> &'static str {
+    /// Section name in config.toml and tuned-params.json
+    fn config_key(self) -> &'static str {
         match self {
-            Self::PowerSaver => "power",
-            Self::Balanced => "balance_power",
+            Self::PowerSaver => "power_saver",
+            Self::Balanced => "balanced",
             Self::Performance => "performance",
         }
     }
-
-    fn ceiling(self) -> u64 {
-        match self {
-            Self::PowerSaver => 3_500_000,   // 3.5 GHz — no point going higher for fanless
-            Self::Balanced => 4_500_000,
-            Self::Performance => 4_500_000,
-        }
-    }
 }
 
 // =============================================================================
-// Thermal table: 4 levels per profile
+// Thermal table: any number of levels per profile
 // =============================================================================
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Polls a step-down into a level holds off stepping back up, by default
+const COOLDOWN: u32 = 3;
+
+/// °C either side of the setpoint the predictive law lets a cap ride
+const BAND: f64 = 1.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "StoredTable")]
 struct ThermalTable {
     /// Cap when below all thresholds (full power for this profile)
     max_cap: u64,
-    /// Temp thresholds °C ascending: when temp > thresholds[i], apply caps[i]
-    thresholds: [i32; 4],
-    /// Freq caps kHz descending: caps[i] = cap when temp > thresholds[i]
-    caps: [u64; 4],
-    /// Hysteresis °C for stepping back up
+    /// Ascending thresholds, descending caps: when temp > levels[i].above,
+    /// the cap is levels[i].cap or lower
+    levels: Vec<Level>,
+    /// Slide the cap linearly between levels instead of stepping
+    #[serde(default, skip_serializing_if = "is_false")]
+    interpolate: bool,
+    /// Levels for the other core types of a hybrid CPU, highest first and
+    /// spread evenly from `max_cap` to the lowest cap. Core types without
+    /// one lag the P-cores by a level.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    ladders: BTreeMap<CoreType, Vec<u64>>,
+    /// Learned package power (W) this profile can hold without running
+    /// hot; PL1 never goes above it. Unset until it first ran hot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sustainable_w: Option<f64>,
+    /// Learned shift of the fan curve in °C; negative spins up earlier
+    #[serde(default, skip_serializing_if = "is_zero")]
+    fan_offset: i32,
+    /// Gains for the PID controller, refined by the auto-tuner
+    #[serde(default)]
+    pid: PidGains,
+    /// Heat budget for running at the profile's ceiling in short bursts;
+    /// the budget is learned. Absent or a zero budget: no bursts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    burst: Option<BurstBudget>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Level {
+    /// °C the temperature must exceed to enter this level
+    above: i32,
+    /// Freq cap kHz
+    cap: u64,
+    /// °C below `above` before stepping back up out of this level
     hysteresis: i32,
+    /// Polls a step-down into this level holds off stepping back up
+    #[serde(default = "default_cooldown")]
+    cooldown: u32,
+}
+
+fn default_cooldown() -> u32 {
+    COOLDOWN
+}
+
+/// A table as found in tuned-params.json or the config: `levels`, or the
+/// fixed four-level `thresholds`/`caps`/`hysteresis` written before, which
+/// is migrated on load. In the config the old keys may also override
+/// matching fields of the default levels.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoredTable {
+    max_cap: u64,
+    #[serde(default)]
+    levels: Vec<Level>,
+    #[serde(default)]
+    interpolate: bool,
+    thresholds: Option<Vec<i32>>,
+    caps: Option<Vec<u64>>,
+    hysteresis: Option<i32>,
+    #[serde(default)]
+    ladders: BTreeMap<CoreType, Vec<u64>>,
+    #[serde(default)]
+    sustainable_w: Option<f64>,
+    #[serde(default)]
+    fan_offset: i32,
+    #[serde(default)]
+    pid: PidGains,
+    #[serde(default)]
+    burst: Option<BurstBudget>,
+}
+
+impl TryFrom<StoredTable> for ThermalTable {
+    type Error = String;
+
+    fn try_from(s: StoredTable) -> Result<Self, String> {
+        let mut levels = s.levels;
+        if levels.is_empty() {
+            let (Some(thresholds), Some(caps)) = (&s.thresholds, &s.caps) else {
+                return Err("table needs levels (or thresholds and caps)".into());
+            };
+            if thresholds.len() != caps.len() {
+                return Err(format!("{} thresholds but {} caps", thresholds.len(), caps.len()));
+            }
+            levels = thresholds
+                .iter()
+                .zip(caps)
+                .map(|(&above, &cap)| Level { above, cap, hysteresis: 0, cooldown: COOLDOWN })
+                .collect();
+        }
+        let n = levels.len();
+        if n == 0 {
+            return Err("table needs at least one level".into());
+        }
+        if let Some(thresholds) = s.thresholds {
+            if thresholds.len() != n {
+                return Err(format!("{} thresholds for {n} levels", thresholds.len()));
+            }
+            levels.iter_mut().zip(thresholds).for_each(|(l, t)| l.above = t);
+        }
+        if let Some(caps) = s.caps {
+            if caps.len() != n {
+                return Err(format!("{} caps for {n} levels", caps.len()));
+            }
+            levels.iter_mut().zip(caps).for_each(|(l, c)| l.cap = c);
+        }
+        if let Some(h) = s.hysteresis {
+            levels.iter_mut().for_each(|l| l.hysteresis = h);
+        }
+        Ok(Self {
+            max_cap: s.max_cap,
+            levels,
+            interpolate: s.interpolate,
+            ladders: s.ladders,
+            sustainable_w: s.sustainable_w,
+            fan_offset: s.fan_offset,
+            pid: s.pid,
+            burst: s.burst,
+        })
+    }
+}
+
+/// Output is MHz below the ceiling
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PidGains {
+    /// MHz per °C above the setpoint
+    kp: f64,
+    /// MHz per °C·s
+    ki: f64,
+    /// MHz per °C/s of temperature rise
+    kd: f64,
+}
+
+impl Default for PidGains {
+    fn default() -> Self {
+        Self { kp: 50.0, ki: 6.0, kd: 20.0 }
+    }
+}
+
+fn is_zero(v: &i32) -> bool {
+    *v == 0
+}
+
+fn is_false(v: &bool) -> bool {
+    !*v
+}
+
+/// How far auto-tuning may move the fan curve either way, °C
+const FAN_OFFSET_MAX: i32 = 10;
+
+/// (threshold °C, cap kHz) steps sharing one hysteresis
+fn levels(steps: &[(i32, u64)], hysteresis: i32) -> Vec<Level> {
+    steps.iter().map(|&(above, cap)| Level { above, cap, hysteresis, cooldown: COOLDOWN }).collect()
 }
 
 impl ThermalTable {
-    fn power_saver() -> Self {
+    fn new(max_cap: u64, levels: Vec<Level>) -> Self {
         Self {
-            max_cap: 3_000_000,        // < 50°C → 3.0 GHz
-            thresholds: [50, 55, 58, 62],
-            caps: [2_500_000, 2_000_000, 1_500_000, 1_200_000],
-            hysteresis: 2,
+            max_cap,
+            levels,
+            interpolate: false,
+            ladders: BTreeMap::new(),
+            sustainable_w: None,
+            fan_offset: 0,
+            pid: PidGains::default(),
+            burst: None,
         }
     }
 
+    fn power_saver() -> Self {
+        // < 50°C → 3.0 GHz, the ceiling for UI bursts while the budget lasts
+        let mut t = Self::new(3_000_000, levels(&[(50, 2_500_000), (55, 2_000_000), (58, 1_500_000), (62, 1_200_000)], 2));
+        t.burst = Some(BurstBudget { baseline: 45, budget: 60.0 });
+        t
+    }
+
     fn balanced() -> Self {
-        Self {
-            max_cap: 4_000_000,        // < 65°C → 4.0 GHz
-            thresholds: [65, 72, 78, 83],
-            caps: [3_500_000, 3_000_000, 2_500_000, 2_000_000],
-            hysteresis: 5,
-        }
+        // < 65°C → 4.0 GHz
+        Self::new(4_000_000, levels(&[(65, 3_500_000), (72, 3_000_000), (78, 2_500_000), (83, 2_000_000)], 5))
     }
 
     fn performance() -> Self {
-        Self {
-            max_cap: 4_500_000,        // < 75°C → 4.5 GHz
-            thresholds: [75, 85, 92, 95],
-            caps: [3_800_000, 3_200_000, 2_800_000, 2_200_000],
-            hysteresis: 5,
+        // < 75°C → 4.5 GHz
+        Self::new(4_500_000, levels(&[(75, 3_800_000), (85, 3_200_000), (92, 2_800_000), (95, 2_200_000)], 5))
+    }
+
+    /// All cap levels ordered from highest to lowest: max_cap, then each level's cap
+    fn all_levels(&self) -> Vec<u64> {
+        std::iter::once(self.max_cap).chain(self.levels.iter().map(|l| l.cap)).collect()
+    }
+
+    /// Levels for `core`. The default E-core ladder starts throttling one
+    /// level after the P-cores (LP E-cores two), so background work keeps
+    /// running while the P-cores back off; everything meets at the lowest cap.
+    fn ladder(&self, core: CoreType) -> Vec<u64> {
+        let l = self.all_levels();
+        let lag = match (core, self.ladders.get(&core)) {
+            (CoreType::Performance, _) => return l,
+            (_, Some(ladder)) => return ladder.clone(),
+            (CoreType::Efficient, None) => 1,
+            (CoreType::LowPower, None) => 2,
+        };
+        let last = l.len() - 1;
+        (0..=last).map(|i| if i == last { l[last] } else { l[i.saturating_sub(lag)] }).collect()
+    }
+
+    /// Where `cap` sits on the P-core levels: 0.0 at max_cap, 1.0 at the
+    /// lowest cap, linear between neighbouring levels.
+    fn position(&self, cap: u64) -> f64 {
+        let p = self.all_levels();
+        let n = (p.len() - 1) as f64;
+        if cap >= p[0] {
+            return 0.0;
         }
+        for i in 0..p.len() - 1 {
+            if cap > p[i + 1] {
+                return (i as f64 + (p[i] - cap) as f64 / (p[i] - p[i + 1]).max(1) as f64) / n;
+            }
+        }
+        1.0
+    }
+
+    /// Cap for `core` while the P-cores are at `cap`: the same position
+    /// between levels, on its own ladder.
+    fn core_cap(&self, core: CoreType, cap: u64, limits: &Limits) -> u64 {
+        if core == CoreType::Performance {
+            return cap;
+        }
+        let g: Vec<f64> = self.ladder(core).iter().map(|&c| c as f64).collect();
+        let c = interp(&g, self.position(cap)) as u64;
+        c / limits.freq_step * limits.freq_step
     }
 
-    /// All cap levels ordered from highest to lowest: [max_cap, caps[0], caps[1], caps[2], caps[3]]
-    fn all_levels(&self) -> [u64; 5] {
-        [self.max_cap, self.caps[0], self.caps[1], self.caps[2], self.caps[3]]
+    /// PL1/PL2 in watts while the P-cores are at `cap`
+    fn power_limits(&self, power: &PowerConfig, cap: u64) -> (f64, f64) {
+        let pos = self.position(cap);
+        let pl1 = interp(&power.pl1, pos).min(self.sustainable_w.unwrap_or(f64::MAX));
+        (pl1, interp(&power.pl2, pos).max(pl1))
     }
 
-    /// Find which level index (0-4) the current cap is at or closest below.
+    /// Index into `all_levels` the current cap is at or closest below.
     fn current_level(&self, current_cap: u64) -> usize {
         let levels = self.all_levels();
-        for (i, &cap) in levels.iter().enumerate() {
-            if current_cap >= cap {
-                return i;
+        levels.iter().position(|&cap| current_cap >= cap).unwrap_or(levels.len() - 1)
+    }
+
+    /// Cap the temperature calls for, before any ramping: the level it
+    /// exceeds, or with `interpolate` the point between that level's cap
+    /// and the next one's.
+    fn cap_at(&self, temp: i32, limits: &Limits) -> u64 {
+        let Some(i) = self.levels.iter().rposition(|l| temp > l.above) else {
+            return self.max_cap;
+        };
+        let here = &self.levels[i];
+        match self.levels.get(i + 1) {
+            Some(next) if self.interpolate => {
+                let frac = f64::from(temp - here.above) / f64::from((next.above - here.above).max(1));
+                let cap = here.cap as f64 - (here.cap - next.cap.min(here.cap)) as f64 * frac;
+                (cap as u64 / limits.freq_step * limits.freq_step).max(next.cap)
             }
+            _ => here.cap,
         }
-        4 // at or below lowest
     }
 
     /// Compute target cap given current temp, rate of change, and current cap.
     /// Step-down is immediate (jump to correct level).
     /// Step-up is gradual (+200 MHz per poll, capped at next level, gated by hysteresis).
     /// temp_delta: temperature change since last poll (positive = heating up).
-    fn target_cap(&self, temp: i32, temp_delta: i32, current_cap: u64) -> u64 {
-        let levels = self.all_levels();
-        // thresholds[i] gates transition from levels[i] down to levels[i+1]
-        // i.e., if temp > thresholds[i], you should be at levels[i+1] or lower
-
+    fn target_cap(&self, temp: i32, temp_delta: i32, current_cap: u64, limits: &Limits) -> u64 {
         // Predictive bias: if temp is rising fast, lower effective thresholds
         // so we step down before actually hitting the wall.
         // Use half the delta to avoid over-reacting to transient spikes.
         // e.g., +16°C/poll → bias = 8, effectively triggers 8°C earlier
         let bias = temp_delta.max(0) / 2;
 
-        // Step DOWN: find the correct level for this temperature (immediate)
-        let mut target_level: usize = 0; // default: max_cap
-        for i in 0..4 {
-            if temp + bias > self.thresholds[i] {
-                target_level = i + 1;
-            }
-        }
-        let down_cap = levels[target_level];
-
-        // If we need to step down, do it immediately
+        // Step DOWN: find the correct cap for this temperature (immediate)
+        let down_cap = self.cap_at(temp + bias, limits);
         if down_cap < current_cap {
             return down_cap;
         }
 
-        // Step UP: ramp gradually toward next level, with hysteresis
+        // Step UP: ramp gradually toward the next level, once temp is below
+        // the threshold that pushed us into the current one minus its hysteresis
         let cur_level = self.current_level(current_cap);
         if cur_level > 0 {
-            // To step up from cur_level to cur_level-1, temp must be below
-            // the threshold that pushed us into cur_level, minus hysteresis
-            let thresh_idx = cur_level - 1; // thresholds[thresh_idx] caused us to drop to cur_level
-            let up_thresh = self.thresholds[thresh_idx] - self.hysteresis;
-            if temp < up_thresh {
-                let next_level_cap = levels[cur_level - 1];
-                return (current_cap + FREQ_STEP * 2).min(next_level_cap); // +200 MHz toward next level
+            let entered = &self.levels[cur_level - 1];
+            let up_cap = if self.interpolate {
+                self.cap_at(temp + entered.hysteresis, limits)
+            } else if temp < entered.above - entered.hysteresis {
+                self.all_levels()[cur_level - 1]
+            } else {
+                current_cap
+            };
+            if up_cap > current_cap {
+                return (current_cap + limits.freq_step * 2).min(up_cap); // +2 steps toward next level
             }
         }
 
         current_cap
     }
 
+    /// Polls to hold off stepping up after a step-down to `cap`
+    fn cooldown_at(&self, cap: u64) -> u32 {
+        match self.current_level(cap) {
+            0 => 0,
+            i => self.levels[i - 1].cooldown,
+        }
+    }
+
     fn caps_str(&self) -> String {
-        format!(
-            "{}/{}/{}/{}/{}",
-            freq_ghz(self.max_cap),
-            freq_ghz(self.caps[0]),
-            freq_ghz(self.caps[1]),
-            freq_ghz(self.caps[2]),
-            freq_ghz(self.caps[3]),
-        )
+        self.all_levels().iter().map(|&c| freq_ghz(c)).collect::<Vec<_>>().join("/")
     }
 
     fn thresholds_str(&self) -> String {
-        format!(
-            "{}/{}/{}/{}°C",
-            self.thresholds[0], self.thresholds[1], self.thresholds[2], self.thresholds[3],
-        )
+        let t: Vec<String> = self.levels.iter().map(|l| l.above.to_string()).collect();
+        format!("{}°C", t.join("/"))
+    }
+
+    fn hysteresis_str(&self) -> String {
+        let h: Vec<String> = self.levels.iter().map(|l| l.hysteresis.to_string()).collect();
+        format!("{}°C", h.join("/"))
     }
 
     fn lowest_cap(&self) -> u64 {
-        self.caps[3]
+        self.levels.last().map_or(self.max_cap, |l| l.cap)
+    }
+
+    /// The first threshold: where throttling starts
+    fn first_threshold(&self) -> i32 {
+        self.levels[0].above
+    }
+
+    /// The threshold before the last: running warm but not in danger
+    fn warm_threshold(&self) -> i32 {
+        self.levels[self.levels.len().saturating_sub(2)].above
+    }
+
+    /// The last threshold, guarding the lowest cap
+    fn hot_threshold(&self) -> i32 {
+        self.levels[self.levels.len() - 1].above
     }
 
-    fn enforce_invariants(&mut self, ceiling: u64) {
+    fn enforce_invariants(&mut self, ceiling: u64, limits: &Limits) {
+        let (min_cap, min_spread) = (limits.min_cap, limits.min_spread);
+
         // Clamp max_cap to profile ceiling
-        self.max_cap = self.max_cap.clamp(MIN_CAP, ceiling);
+        self.max_cap = self.max_cap.clamp(min_cap, ceiling);
 
         // Enforce monotonically decreasing with minimum spread:
-        // max_cap > caps[0] > caps[1] > caps[2] > caps[3]
+        // max_cap > levels[0].cap > levels[1].cap > …
         let mut prev = self.max_cap;
-        for c in &mut self.caps {
-            let upper = if prev > MIN_CAP + MIN_SPREAD {
-                prev - MIN_SPREAD
+        for level in &mut self.levels {
+            let upper = if prev > min_cap + min_spread {
+                prev - min_spread
             } else {
-                MIN_CAP
+                min_cap
             };
-            if *c > upper {
-                *c = upper;
-            }
-            if *c < MIN_CAP {
-                *c = MIN_CAP;
+            level.cap = level.cap.min(upper).max(min_cap);
+            prev = level.cap;
+        }
+
+        self.fan_offset = self.fan_offset.clamp(-FAN_OFFSET_MAX, FAN_OFFSET_MAX);
+
+        // Other core types: within limits and never rising with temperature
+        self.ladders.remove(&CoreType::Performance);
+        for ladder in self.ladders.values_mut() {
+            let mut prev = ceiling;
+            for c in ladder.iter_mut() {
+                *c = (*c).clamp(min_cap, prev);
+                prev = *c;
             }
-            prev = *c;
         }
     }
 }
 
+/// Linear interpolation along a ladder of any length at position 0.0..=1.0
+fn interp(ladder: &[f64], pos: f64) -> f64 {
+    let Some(last) = ladder.len().checked_sub(1).filter(|&l| l > 0) else {
+        return ladder.first().copied().unwrap_or(0.0);
+    };
+    let x = pos.clamp(0.0, 1.0) * last as f64;
+    let i = (x.floor() as usize).min(last - 1);
+    ladder[i] + (ladder[i + 1] - ladder[i]) * (x - i as f64)
+}
+
 // =============================================================================
 // Persisted state
 // =============================================================================
@@ -236,33 +490,67 @@ struct State {
     power_saver: ThermalTable,
     balanced: ThermalTable,
     performance: ThermalTable,
+    /// Fitted from every poll whatever the profile: the machine is the same
+    #[serde(default)]
+    model: ThermalModel,
+    /// Dry runs read the learned state but never write it back
+    #[serde(skip)]
+    read_only: bool,
 }
 
-impl Default for State {
-    fn default() -> Self {
+impl State {
+    /// Starting tables from the config, before any tuning
+    fn from_config(cfg: &Config) -> Self {
         Self {
-            power_saver: ThermalTable::power_saver(),
-            balanced: ThermalTable::balanced(),
-            performance: ThermalTable::performance(),
+            power_saver: cfg.power_saver.table.clone(),
+            balanced: cfg.balanced.table.clone(),
+            performance: cfg.performance.table.clone(),
+            model: ThermalModel::default(),
+            read_only: false,
         }
     }
-}
 
-impl State {
-    fn load() -> Self {
-        match fs::read_to_string(STATE_FILE) {
+    fn load(cfg: &Config) -> Self {
+        let mut state = match fs::read_to_string(STATE_FILE) {
             Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
                 log("tuner", &format!("Bad state file ({e}), using defaults"));
-                Self::default()
+                Self::from_config(cfg)
             }),
             Err(_) => {
                 log("tuner", "No saved state, using defaults");
-                Self::default()
+                Self::from_config(cfg)
+            }
+        };
+        // Limits may have been tightened in the config since the state was saved
+        state.enforce_invariants(cfg);
+        state
+    }
+
+    fn enforce_invariants(&mut self, cfg: &Config) {
+        for p in [Profile::PowerSaver, Profile::Balanced, Profile::Performance] {
+            self.table_mut(p).enforce_invariants(cfg.profile(p).ceiling, &cfg.limits);
+        }
+    }
+
+    /// Move to a reloaded config. Tables the admin changed in the config
+    /// replace the tuned ones; the rest keep what was learned, re-clamped to
+    /// the new limits. Returns the profiles whose tables were replaced.
+    fn reconfigure(&mut self, old: &Config, new: &Config) -> Vec<&'static str> {
+        let mut replaced = Vec::new();
+        for p in [Profile::PowerSaver, Profile::Balanced, Profile::Performance] {
+            if old.profile(p).table != new.profile(p).table {
+                *self.table_mut(p) = new.profile(p).table.clone();
+                replaced.push(p.name());
             }
         }
+        self.enforce_invariants(new);
+        replaced
     }
 
     fn save(&self) {
+        if self.read_only {
+            return;
+        }
         if let Some(dir) = std::path::Path::new(STATE_FILE).parent() {
             let _ = fs::create_dir_all(dir);
         }
@@ -296,121 +584,191 @@ impl State {
 // Tune statistics (rolling window)
 // =============================================================================
 
+/// °C past the setpoint that counts as overshoot
+const OVERSHOOT: i32 = 5;
+
+/// Mean busy fraction from which a window counts as under load
+const LOADED_UTIL: f64 = 0.3;
+/// Mean CPU pressure that counts too: few busy CPUs, but work queued on them
+const LOADED_PRESSURE: f64 = 0.1;
+
+/// Polls are weighted by the seconds they stand for, so shares and means
+/// hold whatever the poll interval was.
 #[derive(Default)]
 struct TuneStats {
     samples: u32,
-    fan_active: u32,
+    secs: f64,
+    fan_active: f64,
     max_temp: i32,
-    temp_sum: i64,
-    at_lowest: u32,
+    /// °C·s
+    temp_sum: f64,
+    at_lowest: f64,
+    /// J, over power_secs
+    power_sum: f64,
+    power_secs: f64,
+    /// Busy fraction and CPU pressure, each over the seconds it was known
+    util_sum: f64,
+    util_secs: f64,
+    /// °C·s over the seconds known to be idle
+    idle_temp_sum: f64,
+    idle_secs: f64,
+    pressure_sum: f64,
+    pressure_secs: f64,
+    /// Seconds under our fan curve, and at its top level
+    fan_controlled: f64,
+    fan_top: f64,
+    /// Firmware thermal throttle events (package + core)
+    throttle_events: u32,
+    /// Seconds more than OVERSHOOT °C past the profile's setpoint
+    overshoot: f64,
+    /// Cap moves that reversed the previous one's direction
+    cap_reversals: u32,
+    last_dir: i8,
+    /// Shallowest depth into the burst budget (°C·s) the fans came on at
+    burst_fan_depth: Option<f64>,
+    /// Bursts that drained the budget with the fans still off
+    burst_quiet_outs: u32,
 }
 
 impl TuneStats {
-    fn record(&mut self, temp: i32, fan_rpm: u32, current_cap: u64, lowest_cap: u64) {
+    /// One poll standing for the `secs` before it, over which the
+    /// temperature peaked at `peak` and averaged `mean`
+    fn record(&mut self, peak: i32, mean: f64, fan_rpm: u32, current_cap: u64, lowest_cap: u64, secs: f64) {
         self.samples += 1;
-        self.temp_sum += temp as i64;
-        if temp > self.max_temp {
-            self.max_temp = temp;
+        self.secs += secs;
+        self.temp_sum += mean * secs;
+        if peak > self.max_temp {
+            self.max_temp = peak;
         }
         if fan_rpm > 100 {
-            self.fan_active += 1;
+            self.fan_active += secs;
         }
         if current_cap == lowest_cap {
-            self.at_lowest += 1;
+            self.at_lowest += secs;
         }
     }
 
-    fn avg_temp(&self) -> i32 {
-        if self.samples == 0 { 0 } else { (self.temp_sum / self.samples as i64) as i32 }
+    fn record_power(&mut self, watts: f64, secs: f64) {
+        self.power_sum += watts * secs;
+        self.power_secs += secs;
     }
 
-    fn fan_pct(&self) -> u32 {
-        if self.samples == 0 { 0 } else { self.fan_active * 100 / self.samples }
+    fn record_load(&mut self, util: Option<f64>, pressure: Option<f64>, secs: f64) {
+        if let Some(u) = util {
+            self.util_sum += u * secs;
+            self.util_secs += secs;
+        }
+        if let Some(p) = pressure {
+            self.pressure_sum += p * secs;
+            self.pressure_secs += secs;
+        }
     }
 
-    fn lowest_pct(&self) -> u32 {
-        if self.samples == 0 { 0 } else { self.at_lowest * 100 / self.samples }
+    fn record_setpoint(&mut self, temp: i32, setpoint: i32, secs: f64) {
+        if temp > setpoint + OVERSHOOT {
+            self.overshoot += secs;
+        }
     }
-}
 
-// =============================================================================
-// Hardware I/O
-// =============================================================================
+    /// Temperature while utilization stayed under LOADED_UTIL
+    fn record_idle_temp(&mut self, util: Option<f64>, mean: f64, secs: f64) {
+        if util.is_some_and(|u| u < LOADED_UTIL) {
+            self.idle_temp_sum += mean * secs;
+            self.idle_secs += secs;
+        }
+    }
 
-fn read_sysfs_i64(path: &str) -> Option<i64> {
-    fs::read_to_string(path).ok()?.trim().parse().ok()
-}
+    fn record_cap_change(&mut self, old: u64, new: u64) {
+        let dir = if new > old { 1 } else if new < old { -1 } else { return };
+        if self.last_dir == -dir {
+            self.cap_reversals += 1;
+        }
+        self.last_dir = dir;
+    }
 
-fn cpu_temp() -> i32 {
-    read_sysfs_i64(TEMP_SENSOR).map(|t| (t / 1000) as i32).unwrap_or(0)
-}
+    fn reversals_per_min(&self) -> f64 {
+        if self.secs > 0.0 { f64::from(self.cap_reversals) * 60.0 / self.secs } else { 0.0 }
+    }
 
-fn fan_rpm() -> u32 {
-    let f1 = read_sysfs_i64(FAN1_SENSOR).unwrap_or(0) as u32;
-    let f2 = read_sysfs_i64(FAN2_SENSOR).unwrap_or(0) as u32;
-    f1.max(f2)
-}
+    fn record_bursts(&mut self, (fan_depth, quiet_outs): (Option<f64>, u32)) {
+        if let Some(d) = fan_depth {
+            self.burst_fan_depth = Some(self.burst_fan_depth.map_or(d, |o| o.min(d)));
+        }
+        self.burst_quiet_outs += quiet_outs;
+    }
 
-fn cpufreq_dirs() -> Vec<PathBuf> {
-    let mut dirs = Vec::new();
-    if let Ok(entries) = fs::read_dir("/sys/devices/system/cpu/") {
-        for entry in entries.flatten() {
-            let name = entry.file_name();
-            let s = name.to_string_lossy();
-            if s.starts_with("cpu")
-                && s.len() > 3
-                && s.as_bytes()[3].is_ascii_digit()
-            {
-                let p = entry.path().join("cpufreq");
-                if p.is_dir() {
-                    dirs.push(p);
-                }
-            }
+    fn record_throttle(&mut self, events: u32) {
+        self.throttle_events = self.throttle_events.saturating_add(events);
+    }
+
+    fn record_fan_level(&mut self, level: u8, secs: f64) {
+        self.fan_controlled += secs;
+        if level >= fan::MAX_LEVEL {
+            self.fan_top += secs;
         }
     }
-    dirs.sort();
-    dirs
-}
 
-fn set_max_freq(dirs: &[PathBuf], freq: u64) {
-    let val = freq.to_string();
-    for d in dirs {
-        let _ = fs::write(d.join("scaling_max_freq"), &val);
+    /// Share of controlled time at full fan, `None` if firmware ran the fan
+    fn fan_top_pct(&self) -> Option<u32> {
+        (self.fan_controlled > 0.0).then(|| (self.fan_top * 100.0 / self.fan_controlled) as u32)
     }
-}
 
-fn apply_base(dirs: &[PathBuf], min_freq: u64, epp: &str, boost: u8) {
-    let min_val = min_freq.to_string();
-    for d in dirs {
-        let _ = fs::write(d.join("scaling_min_freq"), &min_val);
-        let _ = fs::write(d.join("energy_performance_preference"), epp);
+    fn avg_power(&self) -> Option<f64> {
+        (self.power_secs > 0.0).then(|| self.power_sum / self.power_secs)
     }
-    let _ = fs::write(HWP_BOOST_PATH, boost.to_string());
-}
 
-fn detect_profile() -> Option<Profile> {
-    let out = Command::new("gdbus")
-        .args([
-            "call", "--system",
-            "--dest", "net.hadess.PowerProfiles",
-            "--object-path", "/net/hadess/PowerProfiles",
-            "--method", "org.freedesktop.DBus.Properties.Get",
-            "net.hadess.PowerProfiles", "ActiveProfile",
-        ])
-        .output()
-        .ok()?;
-    let s = String::from_utf8_lossy(&out.stdout);
-    ["power-saver", "balanced", "performance"]
-        .iter()
-        .find(|name| s.contains(*name))
-        .and_then(|name| Profile::parse(name))
+    fn avg_util(&self) -> Option<f64> {
+        (self.util_secs > 0.0).then(|| self.util_sum / self.util_secs)
+    }
+
+    /// Mean idle temperature, given 20 s of idle to go by
+    fn avg_idle_temp(&self) -> Option<f64> {
+        (self.idle_secs >= 20.0).then(|| self.idle_temp_sum / self.idle_secs)
+    }
+
+    fn avg_pressure(&self) -> Option<f64> {
+        (self.pressure_secs > 0.0).then(|| self.pressure_sum / self.pressure_secs)
+    }
+
+    /// Whether the window saw real work, `None` where utilization isn't
+    /// known: busy CPUs, or tasks waiting for one
+    fn loaded(&self) -> Option<bool> {
+        let util = self.avg_util()?;
+        Some(util >= LOADED_UTIL || self.avg_pressure().is_some_and(|p| p >= LOADED_PRESSURE))
+    }
+
+    fn avg_temp(&self) -> i32 {
+        if self.secs > 0.0 { (self.temp_sum / self.secs) as i32 } else { 0 }
+    }
+
+    fn pct(&self, secs: f64) -> u32 {
+        if self.secs > 0.0 { (secs * 100.0 / self.secs) as u32 } else { 0 }
+    }
+
+    fn fan_pct(&self) -> u32 {
+        self.pct(self.fan_active)
+    }
+
+    fn lowest_pct(&self) -> u32 {
+        self.pct(self.at_lowest)
+    }
 }
 
 // =============================================================================
 // Logging helpers
 // =============================================================================
 
+/// Set by the simulator so hours of virtual time don't flood stderr
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
 fn log(tag: &str, msg: &str) {
+    if QUIET.load(Ordering::Relaxed) {
+        return;
+    }
     let ts = timestamp();
     eprintln!("[{ts}] [{tag}] {msg}");
 }
@@ -427,283 +785,1164 @@ fn freq_ghz(freq: u64) -> String {
     format!("{:.1}", freq as f64 / 1_000_000.0)
 }
 
-fn clamp_freq(freq: u64) -> u64 {
-    freq.clamp(MIN_CAP, MAX_CAP)
+fn fmt_secs(secs: f64) -> String {
+    let s = secs as u64;
+    format!("{}h{:02}m{:02}s", s / 3600, s / 60 % 60, s % 60)
+}
+
+/// Growth per second of a counter between two timed reads, `None` if
+/// either is missing or it wrapped
+fn per_sec(before: Option<(u64, Duration)>, after: Option<(u64, Duration)>) -> Option<f64> {
+    match (before, after) {
+        (Some((v0, t0)), Some((v1, t1))) if v1 >= v0 && t1 > t0 => Some((v1 - v0) as f64 / (t1 - t0).as_secs_f64()),
+        _ => None,
+    }
 }
 
 // =============================================================================
 // Auto-tuning
 // =============================================================================
 
-fn auto_tune(profile: Profile, stats: &TuneStats, state: &mut State) {
-    if stats.samples < 10 {
-        return;
+/// Returns a short name for the adjustment made, if any.
+fn auto_tune(profile: Profile, stats: &TuneStats, state: &mut State, cfg: &Config) -> Option<&'static str> {
+    if stats.secs < 20.0 {
+        return None;
     }
 
     let max = stats.max_temp;
     let fan_pct = stats.fan_pct();
     let lowest_pct = stats.lowest_pct();
     let avg = stats.avg_temp();
+    // Cool while idle says nothing about headroom, and fans spinning while
+    // idle are the room's doing. Without utilization, fall back on a warm
+    // average for power-saver and assume load elsewhere.
+    let loaded = stats.loaded();
+    let load = stats.avg_util().map_or(format!("avg={avg}°C"), |u| format!("util={:.0}%", u * 100.0));
+    let l = &cfg.limits;
+    let step = l.freq_step;
+    let pc = cfg.profile(profile);
+    let (pid, setpoint) = (pc.controller == ControlMode::Pid, pc.setpoint);
+    // The levels are only tuned while they set the caps. PID and a trusted
+    // model steer below max_cap and leave them alone.
+    let levels = match pc.controller {
+        ControlMode::Table => true,
+        ControlMode::Pid => false,
+        ControlMode::Predictive => !state.model.trusted(),
+    };
     let t = state.table_mut(profile);
 
-    match profile {
-        Profile::PowerSaver => {
-            if fan_pct == 0 && max < t.thresholds[2] && avg >= 48 {
-                // Fans OFF under actual load → raise max_cap only (not step-down caps)
-                t.max_cap = clamp_freq(t.max_cap + FREQ_STEP);
-                log("tuner", &format!("[ps] Fans OFF under load avg={avg}°C → max_cap +100MHz"));
-            } else if fan_pct > 20 {
-                // Fans active too much → lower all caps
-                t.max_cap = clamp_freq(t.max_cap.saturating_sub(FREQ_STEP));
-                for c in &mut t.caps { *c = clamp_freq(c.saturating_sub(FREQ_STEP)); }
-                log("tuner", &format!("[ps] Fans {fan_pct}% → all caps -100MHz"));
-            } else if fan_pct > 0 {
-                // Occasional fan → tighten threshold
-                t.thresholds[0] = (t.thresholds[0] - 1).clamp(40, 55);
-                log("tuner", &format!("[ps] Fan blips ({fan_pct}%) → thresh[0]={}", t.thresholds[0]));
-            }
-        }
-        Profile::Balanced => {
-            if max < (t.thresholds[2] - 5) && lowest_pct == 0 {
-                t.max_cap = clamp_freq(t.max_cap + FREQ_STEP);
-                t.caps[0] = clamp_freq(t.caps[0] + FREQ_STEP);
-                log("tuner", &format!("[bal] Headroom max={max}°C → top caps +100MHz"));
-            } else if max > t.thresholds[3] {
-                t.max_cap = clamp_freq(t.max_cap.saturating_sub(FREQ_STEP));
-                t.caps[0] = clamp_freq(t.caps[0].saturating_sub(FREQ_STEP));
-                log("tuner", &format!("[bal] Hot max={max}°C → top caps -100MHz"));
-            }
-        }
-        Profile::Performance => {
-            if max < (t.thresholds[2] - 3) && lowest_pct == 0 {
-                t.max_cap = clamp_freq(t.max_cap + FREQ_STEP);
-                t.caps[0] = clamp_freq(t.caps[0] + FREQ_STEP);
-                log("tuner", &format!("[perf] Headroom max={max}°C → top caps +100MHz"));
-            } else if max > 95 {
-                t.max_cap = clamp_freq(t.max_cap.saturating_sub(FREQ_STEP * 2));
-                t.caps[0] = clamp_freq(t.caps[0].saturating_sub(FREQ_STEP * 2));
-                t.caps[1] = clamp_freq(t.caps[1].saturating_sub(FREQ_STEP));
-                log("tuner", &format!("[perf] DANGER max={max}°C → aggressive cap reduction"));
-            } else if max > t.thresholds[2] {
-                t.max_cap = clamp_freq(t.max_cap.saturating_sub(FREQ_STEP));
-                t.caps[0] = clamp_freq(t.caps[0].saturating_sub(FREQ_STEP));
-                log("tuner", &format!("[perf] Warm max={max}°C → top caps -100MHz"));
+    // Firmware hard-throttled despite our caps — exactly what the governor
+    // is there to prevent — so the caps come down, whatever else says
+    let throttled = stats.throttle_events > 0;
+    if throttled {
+        t.max_cap = l.clamp(t.max_cap.saturating_sub(step * 2));
+        if levels {
+            for lv in &mut t.levels { lv.cap = l.clamp(lv.cap.saturating_sub(step * 2)); }
+        }
+        log("tuner", &format!(
+            "[{}] Firmware throttled {}× (max={max}°C) → {} -{}MHz",
+            profile.name(), stats.throttle_events, if levels { "all caps" } else { "max_cap" }, step * 2 / 1000,
+        ));
+    }
+
+    // Fan curve, co-tuned with the caps: a running-hot window spends fan
+    // before frequency (caps still drop past the top threshold), power-saver
+    // trades frequency for quiet instead
+    let fan_action = match stats.fan_top_pct() {
+        None => None,
+        Some(_) if throttled => (t.fan_offset > -FAN_OFFSET_MAX).then(|| {
+            t.fan_offset = (t.fan_offset - 2).max(-FAN_OFFSET_MAX);
+            "fan_earlier"
+        }),
+        Some(_) if profile == Profile::PowerSaver => {
+            (fan_pct > 0 && max < t.warm_threshold() && t.fan_offset < FAN_OFFSET_MAX).then(|| {
+                t.fan_offset += 1;
+                "fan_later"
+            })
+        }
+        Some(top_pct) if max > t.warm_threshold() && max <= t.hot_threshold() && top_pct < 50 && t.fan_offset > -FAN_OFFSET_MAX => {
+            t.fan_offset = (t.fan_offset - 2).max(-FAN_OFFSET_MAX);
+            Some("fan_earlier")
+        }
+        Some(_) if max < t.first_threshold() && t.fan_offset < 0 => {
+            t.fan_offset += 1;
+            Some("fan_later")
+        }
+        _ => None,
+    };
+    if let Some(a) = fan_action {
+        log("tuner", &format!(
+            "[{}] max={max}°C fan={fan_pct}% → {} (curve {:+}°C)",
+            profile.name(), a.replace('_', " "), t.fan_offset,
+        ));
+    }
+
+    let action = if pid {
+        tune_pid(&mut t.pid, stats, setpoint, throttled).or(fan_action)
+    } else if !levels {
+        throttled.then_some("throttled").or(fan_action)
+    } else {
+        throttled.then_some("throttled").or(fan_action).or_else(|| match profile {
+            Profile::PowerSaver => {
+                if fan_pct == 0 && max < t.warm_threshold() && loaded.unwrap_or(avg >= 48) {
+                    // Fans OFF under actual load → raise max_cap only (not step-down caps)
+                    t.max_cap = l.clamp(t.max_cap + step);
+                    log("tuner", &format!("[ps] Fans OFF under load {load} → max_cap +{}MHz", step / 1000));
+                    Some("raise_max")
+                } else if fan_pct > 0 && loaded == Some(false) {
+                    // Lower caps wouldn't quiet fans the CPU isn't driving
+                    log("tuner", &format!("[ps] Fans {fan_pct}% while idle ({load}) → caps kept"));
+                    None
+                } else if fan_pct > 20 {
+                    // Fans active too much → lower all caps
+                    t.max_cap = l.clamp(t.max_cap.saturating_sub(step));
+                    for lv in &mut t.levels { lv.cap = l.clamp(lv.cap.saturating_sub(step)); }
+                    log("tuner", &format!("[ps] Fans {fan_pct}% → all caps -{}MHz", step / 1000));
+                    Some("lower_all")
+                } else if fan_pct > 0 {
+                    // Occasional fan → tighten threshold
+                    t.levels[0].above = (t.levels[0].above - 1).clamp(40, 55);
+                    log("tuner", &format!("[ps] Fan blips ({fan_pct}%) → thresh[0]={}", t.levels[0].above));
+                    Some("tighten_thresh")
+                } else {
+                    None
+                }
             }
+            Profile::Balanced => {
+                if max < (t.warm_threshold() - 5) && lowest_pct == 0 && loaded.unwrap_or(true) {
+                    t.max_cap = l.clamp(t.max_cap + step);
+                    t.levels[0].cap = l.clamp(t.levels[0].cap + step);
+                    log("tuner", &format!("[bal] Headroom max={max}°C → top caps +{}MHz", step / 1000));
+                    Some("raise_top")
+                } else if max > t.hot_threshold() {
+                    t.max_cap = l.clamp(t.max_cap.saturating_sub(step));
+                    t.levels[0].cap = l.clamp(t.levels[0].cap.saturating_sub(step));
+                    log("tuner", &format!("[bal] Hot max={max}°C → top caps -{}MHz", step / 1000));
+                    Some("lower_top")
+                } else {
+                    None
+                }
+            }
+            Profile::Performance => {
+                if max < (t.warm_threshold() - 3) && lowest_pct == 0 && loaded.unwrap_or(true) {
+                    t.max_cap = l.clamp(t.max_cap + step);
+                    t.levels[0].cap = l.clamp(t.levels[0].cap + step);
+                    log("tuner", &format!("[perf] Headroom max={max}°C → top caps +{}MHz", step / 1000));
+                    Some("raise_top")
+                } else if max > t.hot_threshold() {
+                    t.max_cap = l.clamp(t.max_cap.saturating_sub(step * 2));
+                    t.levels[0].cap = l.clamp(t.levels[0].cap.saturating_sub(step * 2));
+                    if let Some(lv) = t.levels.get_mut(1) {
+                        lv.cap = l.clamp(lv.cap.saturating_sub(step));
+                    }
+                    log("tuner", &format!("[perf] DANGER max={max}°C → aggressive cap reduction"));
+                    Some("danger")
+                } else if max > t.warm_threshold() {
+                    t.max_cap = l.clamp(t.max_cap.saturating_sub(step));
+                    t.levels[0].cap = l.clamp(t.levels[0].cap.saturating_sub(step));
+                    log("tuner", &format!("[perf] Warm max={max}°C → top caps -{}MHz", step / 1000));
+                    Some("lower_top")
+                } else {
+                    None
+                }
+            }
+        })
+    };
+
+    // Sustainable package power: drop below what ran hot, creep up while
+    // pressing against the learned limit stays cool
+    let mut learned = None;
+    if let Some(watts) = stats.avg_power() {
+        let t = state.table_mut(profile);
+        let old = t.sustainable_w;
+        let new = if max > t.warm_threshold() {
+            Some(old.map_or(watts * 0.95, |o| o.min(watts * 0.95)))
+        } else if max < t.first_threshold() && lowest_pct == 0 {
+            old.map(|o| if watts >= o * 0.9 { watts.max(o + 1.0) } else { o })
+        } else {
+            old
+        };
+        let pl1 = &cfg.profile(profile).power.pl1;
+        t.sustainable_w = new.map(|w| (w.clamp(pl1[pl1.len() - 1], pl1[0]) * 10.0).round() / 10.0);
+        if t.sustainable_w != old {
+            log("tuner", &format!(
+                "[{}] avg {watts:.1} W at max={max}°C → sustainable {} W",
+                profile.name(), t.sustainable_w.map_or("?".into(), |w| format!("{w:.1}")),
+            ));
+            learned = Some("sustainable_w");
+        }
+    }
+
+    // Burst budget: just short of where the fans came on, growing while
+    // bursts drain it with the fans still off. Baseline: a little over
+    // where the package idles, so the time between bursts refills it.
+    if let Some(b) = state.table_mut(profile).burst.as_mut().filter(|b| b.budget > 0.0) {
+        if let Some(idle) = stats.avg_idle_temp() {
+            let old = b.baseline;
+            b.baseline = idle.round() as i32 + IDLE_MARGIN;
+            if b.baseline != old {
+                log("tuner", &format!("[{}] bursts: idle at {idle:.0}°C → baseline {}°C", profile.name(), b.baseline));
+                learned = learned.or(Some("burst_baseline"));
+            }
+        }
+        let old = b.budget;
+        if let Some(depth) = stats.burst_fan_depth {
+            b.budget = b.budget.min(depth * 0.9);
+        } else if stats.burst_quiet_outs > 0 {
+            b.budget *= 1.2;
+        }
+        b.budget = b.budget.clamp(BUDGET_MIN, BUDGET_MAX).round();
+        if b.budget != old {
+            let why = match stats.burst_fan_depth {
+                Some(d) => format!("fans came on {d:.0}°C·s in"),
+                None => format!("{} run out with fans off", stats.burst_quiet_outs),
+            };
+            log("tuner", &format!("[{}] bursts: {why} → budget {:.0}°C·s", profile.name(), b.budget));
+            learned = learned.or(Some("burst_budget"));
         }
     }
 
     // Enforce invariants after any adjustment
-    state.table_mut(profile).enforce_invariants(profile.ceiling());
+    state.table_mut(profile).enforce_invariants(cfg.profile(profile).ceiling, l);
 
     let t = state.table(profile);
     log("tuner", &format!(
-        "[{}] samples={} avg={avg}°C max={max}°C fan={fan_pct}% lowest={lowest_pct}% caps={} thresh={}",
-        profile.name(), stats.samples, t.caps_str(), t.thresholds_str(),
+        "[{}] samples={} avg={avg}°C max={max}°C fan={fan_pct}% lowest={lowest_pct}% throttled={} caps={} thresh={}",
+        profile.name(), stats.samples, stats.throttle_events, t.caps_str(), t.thresholds_str(),
+    ));
+    action.or(learned)
+}
+
+/// PID gains from how the last window went. Overshoot that held for a
+/// while, or a firmware throttle, always means more gain: more `kd` to damp
+/// caps already swinging back and forth, more `kp` otherwise (a load step
+/// caught a poll before the cap could move is no gain problem). Swinging
+/// without it means too much, sitting above the setpoint on average too
+/// little integral. A ±1°C limit cycle is quantisation, not instability.
+fn tune_pid(g: &mut PidGains, stats: &TuneStats, setpoint: i32, throttled: bool) -> Option<&'static str> {
+    let offset = stats.avg_temp() - setpoint;
+    let overshot = throttled || stats.pct(stats.overshoot) >= 5;
+    let swinging = stats.reversals_per_min() > 7.5;
+    let action = if overshot && swinging {
+        g.kd *= 1.25;
+        "pid_kd_up"
+    } else if overshot {
+        g.kp *= 1.25;
+        "pid_kp_up"
+    } else if swinging {
+        g.kp *= 0.8;
+        g.ki *= 0.8;
+        "pid_calmer"
+    } else if offset >= 2 {
+        g.ki *= 1.25;
+        "pid_ki_up"
+    } else {
+        return None;
+    };
+    g.kp = g.kp.clamp(5.0, 200.0);
+    g.ki = g.ki.clamp(0.1, 20.0);
+    g.kd = g.kd.clamp(5.0, 200.0);
+    log("tuner", &format!(
+        "[pid] max={}°C avg={}°C (setpoint {setpoint}, {}% over) reversals={:.1}/min → kp={:.0} ki={:.1} kd={:.0}",
+        stats.max_temp, stats.avg_temp(), stats.pct(stats.overshoot), stats.reversals_per_min(), g.kp, g.ki, g.kd,
     ));
+    Some(action)
+}
+
+// =============================================================================
+// Controller: table lookup + step-up cooldown, or PID toward a setpoint
+// =============================================================================
+
+/// Rise in utilization between polls that counts as a load jump
+const LOAD_JUMP: f64 = 0.3;
+
+/// Per-run control state carried between polls.
+struct Controller {
+    cap: u64,
+    /// Nominal polls to wait before allowing step-up
+    cooldown: u32,
+    /// PID integral term: MHz below the ceiling
+    integral: f64,
+    /// Nominal poll interval; step-ups and cooldowns advance once per
+    /// interval however often the governor polls in between
+    tick_secs: f64,
+    since_tick: f64,
+    credits: Credits,
+    /// Utilization and CPU pressure at the previous poll, for load jumps
+    last_util: Option<f64>,
+    last_pressure: Option<f64>,
+}
+
+/// One poll's readings, as the control laws see them (filtered)
+#[derive(Clone, Copy)]
+struct Reading {
+    temp: i32,
+    /// °C/s
+    rate: f64,
+    /// Seconds since the previous poll
+    dt: f64,
+    /// CPU busy fraction over that time
+    util: Option<f64>,
+    /// Share of that time some task waited for a CPU
+    pressure: Option<f64>,
+    fan_on: bool,
+}
+
+impl Reading {
+    fn new(f: filter::Filtered, dt: f64, util: Option<f64>, pressure: Option<f64>, fan_rpm: u32) -> Self {
+        Self { temp: f.temp.round() as i32, rate: f.rate, dt, util, pressure, fan_on: fan_rpm > 100 }
+    }
+
+    /// Change over `secs` at the estimated rate
+    fn rise(&self, secs: f64) -> i32 {
+        (self.rate * secs).round() as i32
+    }
+}
+
+/// Outcome of one control step
+struct Decision {
+    /// `target_cap` before cooldown was applied
+    target: u64,
+    new_cap: u64,
+}
+
+impl Controller {
+    fn new(cap: u64, tick_secs: f64) -> Self {
+        Self {
+            cap,
+            cooldown: 0,
+            integral: 0.0,
+            tick_secs,
+            since_tick: tick_secs,
+            credits: Credits::default(),
+            last_util: None,
+            last_pressure: None,
+        }
+    }
+
+    /// One step of the profile's control law.
+    fn decide(&mut self, pc: &ProfileConfig, table: &ThermalTable, model: &ThermalModel, limits: &Limits, r: &Reading) -> Decision {
+        self.since_tick += r.dt;
+        // Tolerate float drift from summing sub-intervals
+        let tick = self.since_tick >= self.tick_secs - 1e-6;
+        if tick {
+            self.since_tick = 0.0;
+        }
+        let jump = self.load_jump(r);
+        let r = &Reading { rate: r.rate.max(self.feed_forward(model, r)), ..*r };
+        // The ceiling while burst credits last, the control law after
+        if let Some(b) = table.burst.filter(|b| b.budget > 0.0) {
+            let hot = r.temp > table.first_threshold();
+            if self.credits.update(&b, r.temp, r.dt, r.fan_on, hot, jump) {
+                self.cap = pc.ceiling;
+                self.cooldown = 0;
+                return Decision { target: pc.ceiling, new_cap: pc.ceiling };
+            }
+        }
+        match pc.controller {
+            ControlMode::Table => self.step(table, limits, r.temp, r.rise(self.tick_secs), tick),
+            ControlMode::Pid => self.step_pid(pc, table, limits, r, tick),
+            ControlMode::Predictive if model.trusted() => self.step_predictive(pc, table, model, limits, r, tick),
+            ControlMode::Predictive => self.step(table, limits, r.temp, r.rise(self.tick_secs), tick),
+        }
+    }
+
+    /// Whether utilization or CPU pressure rose by LOAD_JUMP since the
+    /// previous poll. Leaves `last_util` to `feed_forward`.
+    fn load_jump(&mut self, r: &Reading) -> bool {
+        let rose = |before: Option<f64>, now: Option<f64>| matches!((before, now), (Some(b), Some(n)) if n - b >= LOAD_JUMP);
+        let pressure = rose(std::mem::replace(&mut self.last_pressure, r.pressure), r.pressure);
+        rose(self.last_util, r.util) || pressure
+    }
+
+    /// On a load jump, the mean °C/s the model expects over the next
+    /// nominal poll at the new load, ahead of the sensor catching up.
+    /// `f64::MIN` without a jump or a trusted model.
+    fn feed_forward(&mut self, model: &ThermalModel, r: &Reading) -> f64 {
+        let last = std::mem::replace(&mut self.last_util, r.util);
+        match (last, r.util) {
+            (Some(before), Some(now)) if now - before >= LOAD_JUMP && model.trusted() => {
+                let secs = self.tick_secs;
+                (model.predict(r.temp, model::heat(now, self.cap), secs) - f64::from(r.temp)) / secs
+            }
+            _ => f64::MIN,
+        }
+    }
+
+    /// Highest cap (in freq_step steps below the table's max_cap) whose
+    /// predicted temperature `horizon` seconds out stays at or under the
+    /// setpoint. Step-down is immediate and starts a cooldown, step-up
+    /// ramps like the table's once it has run out and the target is clear
+    /// of the cap by more than one step.
+    fn step_predictive(&mut self, pc: &ProfileConfig, table: &ThermalTable, model: &ThermalModel, limits: &Limits, r: &Reading, tick: bool) -> Decision {
+        let util = r.util.unwrap_or(1.0);
+        // The same work at a lower cap keeps the CPUs busier for longer
+        // Raising the cap wants a margin under the setpoint and keeping it
+        // the same over, or the next reading's degree either way flips it
+        let fits = |cap: u64| {
+            let busy = util * self.cap as f64 / cap as f64;
+            let band = if cap > self.cap { -BAND } else { BAND };
+            model.predict(r.temp, model::heat(busy, cap), pc.horizon) <= f64::from(pc.setpoint) + band
+        };
+        let (step, top) = (limits.freq_step, table.max_cap.min(pc.ceiling));
+        let mut target = top;
+        while target > limits.min_cap && !fits(target) {
+            target = target.saturating_sub(step).max(limits.min_cap);
+        }
+        let new_cap = self.hold(target, top, step, tick);
+        Decision { target, new_cap }
+    }
+
+    /// The output is how far (MHz) below the table's max_cap to cap. The
+    /// integral only moves while that stays inside min_cap..=max_cap, or
+    /// when it pulls back out (anti-windup), and ignores the sensor's ±1°C
+    /// of dither. Derivative on temperature rather than error.
+    fn step_pid(&mut self, pc: &ProfileConfig, table: &ThermalTable, limits: &Limits, r: &Reading, tick: bool) -> Decision {
+        let (g, top, dt) = (&table.pid, table.max_cap.min(pc.ceiling), r.dt);
+        let err = f64::from(r.temp - pc.setpoint);
+        let range = top.saturating_sub(limits.min_cap) as f64 / 1000.0;
+        let pd = g.kp * err + g.kd * r.rate;
+
+        let drift = if err.abs() <= 1.0 { 0.0 } else { err };
+        let integral = self.integral + g.ki * drift * dt;
+        let out = pd + integral;
+        let saturated = (out > range && err > 0.0) || (out < 0.0 && err < 0.0);
+        if !saturated {
+            self.integral = integral.clamp(0.0, range);
+        }
+        let below = (pd + self.integral).clamp(0.0, range);
+
+        let step = limits.freq_step;
+        let cap = top - (below * 1000.0) as u64;
+        let target = ((cap + step / 2) / step * step).clamp(limits.min_cap, top);
+        let new_cap = self.hold(target, top, step, tick);
+        Decision { target, new_cap }
+    }
+
+    /// Move toward `target` for the continuous laws: step-down at once and
+    /// into a COOLDOWN, step-up +2 steps per tick after it, and only for a
+    /// target 2 steps clear of the cap (or at `top`), so a reading
+    /// dithering by a degree doesn't walk the cap up and down a step.
+    fn hold(&mut self, target: u64, top: u64, step: u64, tick: bool) -> u64 {
+        let new_cap = if target < self.cap {
+            self.cooldown = COOLDOWN;
+            target
+        } else if tick && self.cooldown == 0 && target == top {
+            (self.cap + step * 2).min(top)
+        } else if tick && self.cooldown == 0 && target >= self.cap + step * 2 {
+            (self.cap + step * 2).min(target - step)
+        } else {
+            self.cap
+        };
+        if tick && new_cap >= self.cap {
+            self.cooldown = self.cooldown.saturating_sub(1);
+        }
+        self.cap = new_cap;
+        new_cap
+    }
+
+    fn step(&mut self, table: &ThermalTable, limits: &Limits, temp: i32, temp_delta: i32, tick: bool) -> Decision {
+        let target = table.target_cap(temp, temp_delta, self.cap, limits);
+
+        // Apply cooldown: suppress step-ups for a few polls after a step-down.
+        // Between nominal polls, step-ups wait for the next one.
+        let new_cap = if target > self.cap && (self.cooldown > 0 || !tick) {
+            if tick {
+                self.cooldown -= 1;
+            }
+            self.cap // hold current cap during cooldown
+        } else {
+            target
+        };
+
+        if new_cap < self.cap {
+            self.cooldown = table.cooldown_at(new_cap); // after step-down, the level's cooldown (default 3 polls, 6s)
+        } else if new_cap > self.cap {
+            self.cooldown = 1; // after step-up, wait 1 poll (2s) for thermal stabilization
+        }
+        self.cap = new_cap;
+        Decision { target, new_cap }
+    }
 }
 
 // =============================================================================
 // Governor loop (runs per profile until stopped)
 // =============================================================================
 
-fn governor(profile: Profile, state: &mut State, stop: &AtomicBool) {
-    let dirs = cpufreq_dirs();
-    if dirs.is_empty() {
+/// What drives a governor run: the time source and messages from main.
+struct Driver<'a> {
+    clock: &'a dyn Clock,
+    /// Validated configs from SIGHUP reloads
+    reload: &'a mpsc::Receiver<Config>,
+    stop: &'a AtomicBool,
+    /// Poked with stop raised, and by the sampler
+    alarm: &'a Alarm,
+}
+
+impl Driver<'_> {
+    /// Sleep for `d`, waking early once stop is raised or a sampled
+    /// temperature rises past `wake_above`
+    fn wait(&self, hw: &mut dyn HardwareBackend, d: Duration, wake_above: Option<i32>) {
+        self.alarm.clear();
+        if !self.stop.load(Ordering::Relaxed) {
+            hw.wait(self.clock, self.alarm, d, wake_above);
+        }
+    }
+}
+
+/// Apply a P-core cap, and what it maps to on each other core type.
+fn set_caps(hw: &mut dyn HardwareBackend, table: &ThermalTable, limits: &Limits, cores: &[CoreType], cap: u64) {
+    for &core in cores {
+        hw.set_max_freq(core, table.core_cap(core, cap, limits));
+    }
+}
+
+/// Turn the controller's cap into writes for the profile's actuator.
+/// Returns the PL1 written, if power limits are in use.
+fn actuate(
+    hw: &mut dyn HardwareBackend,
+    pc: &ProfileConfig,
+    table: &ThermalTable,
+    limits: &Limits,
+    cores: &[CoreType],
+    cap: u64,
+) -> Option<f64> {
+    let power = pc.power.actuator != Actuator::Frequency && hw.has_power_limits();
+    // Power-only leaves frequency alone, unless there's no RAPL to do the job
+    let freq_cap = if power && pc.power.actuator == Actuator::Power { limits.max_cap } else { cap };
+    set_caps(hw, table, limits, cores, freq_cap);
+    if !power {
+        return None;
+    }
+    let (pl1, pl2) = table.power_limits(&pc.power, cap);
+    hw.set_power_limits(Some(((pl1 * 1e6) as u64, (pl2 * 1e6) as u64)));
+    Some(pl1)
+}
+
+/// Profiles driving frequency only get the power limits found at startup
+fn reset_power_limits(hw: &mut dyn HardwareBackend, pc: &ProfileConfig) {
+    if pc.power.actuator == Actuator::Frequency && hw.has_power_limits() {
+        hw.set_power_limits(None);
+    }
+}
+
+fn fmt_pl1(pl1: Option<f64>) -> String {
+    pl1.map_or(String::new(), |w| format!(" PL1={w:.0}W"))
+}
+
+/// °C either side of a threshold (or the setpoint) that counts as close
+const NEAR: i32 = 3;
+/// °C under the first threshold that counts as cold
+const COLD: i32 = 10;
+/// °C/s that counts as moving fast, and as steady
+const FAST_RATE: f64 = 1.5;
+const STEADY_RATE: f64 = 0.25;
+/// Busy fraction above which a cold CPU may not stay cold for long
+const BUSY: f64 = 0.5;
+
+/// Seconds until the next poll: the fastest near or past the last threshold,
+/// or with a `setpoint` law past the setpoint (holding at it is the normal
+/// state under load), or while the temperature moves fast. Backs off by
+/// doubling while cold for the table, steady and mostly idle with the cap
+/// where it wants to be, the nominal interval otherwise. A setpoint law
+/// never backs off while `uncapped`: nothing would stop a load step short
+/// of the setpoint before a backed-off poll.
+fn next_poll(
+    t: &Timing,
+    thresholds: &[i32],
+    setpoint: Option<i32>,
+    r: &Reading,
+    settled: bool,
+    uncapped: bool,
+    last: f64,
+) -> f64 {
+    let close = match setpoint {
+        Some(s) => r.temp > s + NEAR,
+        None => thresholds.iter().any(|&m| (r.temp - m).abs() <= NEAR) || thresholds.iter().all(|&m| r.temp > m),
+    };
+    if close || r.rate.abs() >= FAST_RATE {
+        return t.min_poll_interval;
+    }
+    let cold = match setpoint {
+        Some(s) => !uncapped && r.temp < s - COLD,
+        None => thresholds.iter().all(|&m| r.temp < m - COLD),
+    };
+    let idle = r.util.is_some_and(|u| u < BUSY);
+    if cold && idle && settled && r.rate.abs() < STEADY_RATE {
+        return (last * 2.0).clamp(t.poll_interval, t.max_poll_interval);
+    }
+    t.poll_interval
+}
+
+fn governor(
+    profile: Profile,
+    cfg: &mut Config,
+    state: &mut State,
+    hw: &mut dyn HardwareBackend,
+    telemetry: &mut Telemetry,
+    drv: &Driver,
+) {
+    let clock = drv.clock;
+    if !hw.has_cpufreq() {
         log("gov", "No cpufreq dirs found!");
         return;
     }
 
-    apply_base(&dirs, 400_000, profile.epp(), 1);
+    let epp = &cfg.profile(profile).epp;
+    hw.apply_base(400_000, epp, 1);
+
+    let pc = cfg.profile(profile);
+    if pc.power.actuator != Actuator::Frequency && !hw.has_power_limits() {
+        log(profile.name(), "No RAPL power limits, using frequency caps");
+    }
+    reset_power_limits(hw, pc);
+    if pc.fan.control && !hw.has_fan_control() {
+        log(profile.name(), "No controllable fan, leaving it to firmware");
+    }
+    let fan_curve = pc.fan.control && hw.has_fan_control();
+    let law = match pc.controller {
+        ControlMode::Table => String::new(),
+        ControlMode::Pid => {
+            let g = &state.table(profile).pid;
+            format!(" PID→{}°C (kp={:.0} ki={:.1} kd={:.0})", pc.setpoint, g.kp, g.ki, g.kd)
+        }
+        ControlMode::Predictive => {
+            format!(" predict {}°C/{:.0}s (model {})", pc.setpoint, pc.horizon, state.model.describe())
+        }
+    };
+    if !fan_curve {
+        hw.set_fan_level(None);
+    }
 
-    let mut current_cap = state.table(profile).max_cap;
-    set_max_freq(&dirs, current_cap);
+    let cores = hw.core_types();
+    let mut ctl = Controller::new(state.table(profile).max_cap, cfg.timing.poll_interval);
+    let pl1 = actuate(hw, pc, state.table(profile), &cfg.limits, &cores, ctl.cap);
 
     let t = state.table(profile);
     log(profile.name(), &format!(
-        "Governor started: EPP={} cap={}GHz thresh={} hyst={}°C",
-        profile.epp(), freq_ghz(current_cap), t.thresholds_str(), t.hysteresis,
+        "Governor started: EPP={} cap={}GHz{}{law} thresh={} hyst={}{}",
+        epp, freq_ghz(ctl.cap), fmt_pl1(pl1), t.thresholds_str(), t.hysteresis_str(),
+        if fan_curve { format!(" fan curve {:+}°C", t.fan_offset) } else { String::new() },
     ));
 
     let mut stats = TuneStats::default();
-    let mut last_tune = Instant::now();
-    let mut last_persist = Instant::now();
-    let mut cooldown: u32 = 0; // polls to wait before allowing step-up
-    let mut prev_temp: i32 = cpu_temp();
+    let mut last_tune = clock.now();
+    let mut last_persist = clock.now();
+    let mut prev_temp: Option<i32> = hw.cpu_temp();
+    let mut last_energy = hw.energy_uj().map(|e| (e, clock.now()));
+    let mut last_stall = hw.cpu_stall_us().map(|s| (s, clock.now()));
+    let mut fan_level: Option<u8> = None;
+    let mut last_throttle = hw.throttle_count();
+    let mut filter = SensorFilter::new(&cfg.filter, cfg.timing.poll_interval);
+    let mut fit = FitWindow::default();
+    hw.utilization(); // starts the busy-time window
+    let mut last_poll = clock.now();
+    let mut first_poll = true;
+    let mut interval = cfg.timing.poll_interval;
+    let mut blind = false;
+
+    while !drv.stop.load(Ordering::Relaxed) {
+        // Config swapped in on SIGHUP; cap, cooldown and stats carry over
+        while let Ok(new) = drv.reload.try_recv() {
+            let replaced = state.reconfigure(cfg, &new);
+            let new_epp = &new.profile(profile).epp;
+            if *new_epp != cfg.profile(profile).epp {
+                hw.apply_base(400_000, new_epp, 1);
+            }
+            let repower = new.profile(profile).power != cfg.profile(profile).power;
+            if new.filter != cfg.filter || new.timing.poll_interval != cfg.timing.poll_interval {
+                filter = SensorFilter::new(&new.filter, new.timing.poll_interval);
+            }
+            *cfg = new;
+            ctl.tick_secs = cfg.timing.poll_interval;
+            if repower {
+                reset_power_limits(hw, cfg.profile(profile));
+                actuate(hw, cfg.profile(profile), state.table(profile), &cfg.limits, &cores, ctl.cap);
+            }
+            let t = state.table(profile);
+            log(profile.name(), &format!(
+                "Config reloaded: EPP={} poll={}s caps={} thresh={}{}",
+                cfg.profile(profile).epp, cfg.timing.poll_interval, t.caps_str(), t.thresholds_str(),
+                if replaced.is_empty() { String::new() } else { format!(" (new tables: {})", replaced.join(", ")) },
+            ));
+        }
 
-    while !stop.load(Ordering::Relaxed) {
-        let temp = cpu_temp();
-        let temp_delta = temp - prev_temp;
-        prev_temp = temp;
-        let rpm = fan_rpm();
+        // No temperature → hold the current cap rather than act on garbage
+        let Some(temp) = hw.cpu_temp() else {
+            if !blind {
+                log(profile.name(), "Temperature unavailable, holding cap");
+                blind = true;
+            }
+            // Firmware still sees the temperature
+            if fan_level.take().is_some() {
+                hw.set_fan_level(None);
+            }
+            drv.wait(hw, cfg.timing.poll(), None);
+            // The next reading starts afresh rather than spanning the gap
+            prev_temp = None;
+            last_poll = clock.now();
+            fit.clear();
+            continue;
+        };
+        if blind {
+            log(profile.name(), &format!("Temperature back: {temp}°C"));
+            blind = false;
+            filter.reset();
+        }
+        let prev = prev_temp.replace(temp);
+        let temp_delta = prev.map_or(0, |p| temp - p);
+        let now = clock.now();
+        let dt = (now - last_poll).as_secs_f64();
+        last_poll = now;
+        // What this poll stands for in the tuning stats: the time it
+        // actually covered, the planned interval on the first
+        let secs = if std::mem::take(&mut first_poll) { interval } else { dt };
+        let rpm = hw.fan_rpm();
+        let util = hw.utilization();
+        // CPU pressure: share of the time some task waited for a CPU
+        let stall = hw.cpu_stall_us().map(|s| (s, clock.now()));
+        let pressure = per_sec(last_stall, stall).map(|us| (us / 1e6).min(1.0));
+        last_stall = stall;
+        // The sampler catches spikes that fall between polls: control and
+        // tuning go by the peak, the model by the readings at each poll
+        let span = hw.temp_span();
+        let peak = span.map_or(temp, |s| s.max.max(temp));
+        let filtered = filter.push(peak, now.as_secs_f64());
+        let reading = Reading::new(filtered, dt, util, pressure, rpm);
+
+        // Fan follows the curve, rewritten every poll to feed the watchdog
+        let fan = &cfg.profile(profile).fan;
+        if fan.control && hw.has_fan_control() {
+            let level = fan.level(reading.temp, state.table(profile).fan_offset, fan_level);
+            hw.set_fan_level(Some(level));
+            stats.record_fan_level(level, secs);
+            fan_level = Some(level);
+        } else if fan_level.take().is_some() {
+            hw.set_fan_level(None);
+        }
 
-        let table = state.table(profile);
-        let raw_target = table.target_cap(temp, temp_delta, current_cap);
-        let lowest = table.lowest_cap();
+        // Package power from the RAPL energy counter
+        let energy = hw.energy_uj().map(|e| (e, clock.now()));
+        let power_w = per_sec(last_energy, energy).map(|uj| uj / 1e6);
+        last_energy = energy;
+        if let Some(w) = power_w {
+            stats.record_power(w, secs);
+        }
+        stats.record_load(util, pressure, secs);
+        stats.record_idle_temp(util, span.map_or(f64::from(temp), |s| s.mean), secs);
+
+        // Firmware throttling since the last poll, from the kernel counters
+        let throttle = hw.throttle_count();
+        let throttled = match (last_throttle, throttle) {
+            (Some(before), Some(now)) => now.since(before),
+            _ => ThrottleCount::default(),
+        };
+        last_throttle = throttle.or(last_throttle);
+        let throttle_events = u32::try_from(throttled.total()).unwrap_or(u32::MAX);
+        if throttle_events > 0 {
+            stats.record_throttle(throttle_events);
+            log(profile.name(), &format!(
+                "{temp}°C firmware throttled at {} GHz (package +{}, core +{})",
+                freq_ghz(ctl.cap), throttled.package, throttled.core,
+            ));
+        }
 
-        // Apply cooldown: suppress step-ups for a few polls after a step-down
-        let new_cap = if raw_target > current_cap && cooldown > 0 {
-            cooldown -= 1;
-            current_cap // hold current cap during cooldown
+        // Fit the thermal model on the poll just ended, unless firmware
+        // had the frequency and the cap didn't decide the heat
+        if let (Some(prev), Some(u), 0) = (prev, util, throttle_events) {
+            let fitted = state.model.trusted();
+            let heat = model::heat(u, ctl.cap);
+            if fit.push(&mut state.model, prev, temp, dt, heat, cfg.timing.poll_interval) && !fitted && state.model.trusted() {
+                log(profile.name(), &format!("Thermal model fitted: {}", state.model.describe()));
+            }
         } else {
-            raw_target
-        };
+            fit.clear();
+        }
 
-        stats.record(temp, rpm, current_cap, lowest);
+        let table = state.table(profile);
+        let lowest = table.lowest_cap();
+        let (current_cap, cooldown) = (ctl.cap, ctl.cooldown);
+        let pc = cfg.profile(profile);
+        let d = ctl.decide(pc, table, &state.model, &cfg.limits, &reading);
+
+        stats.record(peak, span.map_or(f64::from(temp), |s| s.mean), rpm, current_cap, lowest, secs);
+        stats.record_setpoint(reading.temp, pc.setpoint, secs);
+        stats.record_cap_change(current_cap, d.new_cap);
+        stats.record_bursts(ctl.credits.take_outcomes());
+        telemetry.record(&Record::Poll {
+            ts: clock.unix_time(),
+            profile: profile.name().into(),
+            temp,
+            temp_delta,
+            fan_rpm: rpm,
+            fan_level,
+            cap: current_cap,
+            target: d.target,
+            cooldown,
+            new_cap: d.new_cap,
+            power_w,
+            util,
+            pressure,
+            peak_temp: span.map(|s| s.max),
+            min_temp: span.map(|s| s.min),
+            mean_temp: span.map(|s| s.mean),
+            filtered_temp: Some(filtered.temp),
+            rate: Some(filtered.rate),
+            credits: ctl.credits.left(),
+            throttle_events,
+        });
 
-        if new_cap != current_cap {
-            set_max_freq(&dirs, new_cap);
-            let arrow = if new_cap < current_cap { "↓" } else { "↑" };
-            log(profile.name(), &format!(
-                "{temp}°C fan:{rpm}rpm {arrow} {}→{} GHz",
-                freq_ghz(current_cap), freq_ghz(new_cap),
-            ));
-            if new_cap < current_cap {
-                cooldown = 3; // after step-down, wait 3 polls (6s) before stepping up
-            } else {
-                cooldown = 1; // after step-up, wait 1 poll (2s) for thermal stabilization
+        if d.new_cap != current_cap {
+            let pl1 = actuate(hw, pc, table, &cfg.limits, &cores, d.new_cap);
+            // PID and the model move a step or two at a time; only the bigger
+            // moves are news
+            let loud = pc.controller == ControlMode::Table || current_cap.abs_diff(d.new_cap) >= 4 * cfg.limits.freq_step;
+            if loud {
+                let arrow = if d.new_cap < current_cap { "↓" } else { "↑" };
+                log(profile.name(), &format!(
+                    "{temp}°C fan:{rpm}rpm {arrow} {}→{} GHz{}",
+                    freq_ghz(current_cap), freq_ghz(d.new_cap), fmt_pl1(pl1),
+                ));
             }
-            current_cap = new_cap;
         }
 
-        if last_tune.elapsed() >= TUNE_INTERVAL {
-            auto_tune(profile, &stats, state);
+        if clock.now() - last_tune >= cfg.timing.tune() {
+            let action = auto_tune(profile, &stats, state, cfg);
+            telemetry.record(&Record::Tune {
+                ts: clock.unix_time(),
+                profile: profile.name().into(),
+                samples: stats.samples,
+                avg_temp: stats.avg_temp(),
+                max_temp: stats.max_temp,
+                fan_pct: stats.fan_pct(),
+                lowest_pct: stats.lowest_pct(),
+                avg_power_w: stats.avg_power(),
+                avg_util: stats.avg_util(),
+                avg_pressure: stats.avg_pressure(),
+                throttle_events: stats.throttle_events,
+                action: action.map(Into::into),
+                table: state.table(profile).clone(),
+            });
             stats = TuneStats::default();
-            last_tune = Instant::now();
+            last_tune = clock.now();
         }
 
-        if last_persist.elapsed() >= PERSIST_INTERVAL {
+        if clock.now() - last_persist >= cfg.timing.persist() {
             state.save();
-            last_persist = Instant::now();
+            last_persist = clock.now();
         }
 
-        thread::sleep(POLL_INTERVAL);
+        // Close to whatever the control law is steering by
+        let pc = cfg.profile(profile);
+        let thresholds: Vec<i32> = state.table(profile).levels.iter().map(|l| l.above).collect();
+        let setpoint = match pc.controller {
+            ControlMode::Pid => Some(pc.setpoint),
+            ControlMode::Predictive if state.model.trusted() => Some(pc.setpoint),
+            _ => None,
+        };
+        let settled = d.new_cap == d.target && ctl.cooldown == 0;
+        let uncapped = d.new_cap >= state.table(profile).max_cap;
+        interval = next_poll(&cfg.timing, &thresholds, setpoint, &reading, settled, uncapped, interval);
+        // A load step between polls wakes us on its way up, in time to act:
+        // short of what the law steers by, or a few degrees on from here
+        let mark = setpoint.unwrap_or_else(|| state.table(profile).first_threshold()) - NEAR;
+        drv.wait(hw, Duration::from_secs_f64(interval), Some(mark.max(reading.temp + NEAR)));
     }
 
     log(profile.name(), "Governor stopped");
 }
 
 // =============================================================================
-// D-Bus monitor
+// Command line
 // =============================================================================
 
-fn watch_dbus(tx: mpsc::Sender<Profile>) {
-    let mut child = match Command::new("dbus-monitor")
-        .args([
-            "--system",
-            "type='signal',interface='org.freedesktop.DBus.Properties',\
-             member='PropertiesChanged',\
-             path='/net/hadess/PowerProfiles'",
-        ])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            log("dbus", &format!("Failed to spawn dbus-monitor: {e}"));
-            return;
+#[derive(Debug)]
+struct Args {
+    config: PathBuf,
+    /// Read real sensors, never write sysfs or the state file
+    dry_run: bool,
+    /// Prefix for all sysfs paths (a fake tree for testing)
+    sysfs_root: Option<PathBuf>,
+    /// Run against the in-memory fake backend at a fixed temperature
+    fake_temp: Option<i32>,
+    /// Skip D-Bus detection and start in this profile
+    profile: Option<Profile>,
+    /// Record every poll and tuner decision as JSONL
+    telemetry: bool,
+}
+
+impl Args {
+    fn parse(argv: &[String]) -> Result<Self, String> {
+        let mut args = Self {
+            config: PathBuf::from(CONFIG_FILE),
+            dry_run: false,
+            sysfs_root: None,
+            fake_temp: None,
+            profile: None,
+            telemetry: false,
+        };
+        let mut it = argv.iter();
+        while let Some(arg) = it.next() {
+            let mut value = |name: &str| it.next().cloned().ok_or(format!("{name} needs a value"));
+            match arg.as_str() {
+                "--config" => args.config = value("--config")?.into(),
+                "--dry-run" => args.dry_run = true,
+                "--sysfs-root" => args.sysfs_root = Some(value("--sysfs-root")?.into()),
+                "--fake-temp" => {
+                    let v = value("--fake-temp")?;
+                    args.fake_temp = Some(v.parse().map_err(|_| format!("bad temperature: {v}"))?);
+                }
+                "--profile" => {
+                    let v = value("--profile")?;
+                    args.profile = Some(Profile::parse(&v).ok_or(format!("unknown profile: {v}"))?);
+                }
+                "--telemetry" => args.telemetry = true,
+                "-h" | "--help" => return Err(USAGE.into()),
+                _ => return Err(format!("unknown argument: {arg}\n{USAGE}")),
+            }
         }
-    };
+        Ok(args)
+    }
 
-    let stdout = child.stdout.take().unwrap();
-    let reader = BufReader::new(stdout);
-    let mut check_next = false;
+    /// Learned state is only persisted when driving the real machine
+    fn read_only(&self) -> bool {
+        self.dry_run || self.fake_temp.is_some() || self.sysfs_root.is_some()
+    }
 
-    for line in reader.lines().map_while(Result::ok) {
-        if line.contains("ActiveProfile") {
-            check_next = true;
-            continue;
+    /// Where to keep the original settings while running, if anywhere
+    fn snapshot_file(&self) -> Option<&'static Path> {
+        (!self.read_only()).then(|| Path::new(SNAPSHOT_FILE))
+    }
+
+    /// P-core frequency range the built-in tables are fitted to
+    fn freq_bounds(&self) -> Option<FreqBounds> {
+        if self.fake_temp.is_some() {
+            return None;
         }
-        if check_next {
-            check_next = false;
-            for name in ["power-saver", "balanced", "performance"] {
-                if line.contains(name) {
-                    if let Some(p) = Profile::parse(name) {
-                        log("dbus", &format!("Profile changed → {name}"));
-                        let _ = tx.send(p);
-                    }
-                    break;
-                }
+        FreqBounds::probe(self.sysfs_root.as_deref().unwrap_or(Path::new("/")))
+    }
+
+    fn backend(&self) -> Box<dyn HardwareBackend> {
+        match self.fake_temp {
+            Some(t) => Box::new(FakeBackend::new(t)),
+            None => {
+                let root = self.sysfs_root.as_deref().unwrap_or(Path::new("/"));
+                Box::new(SysfsBackend::new(root, self.dry_run))
             }
         }
     }
 
-    let _ = child.wait();
+    fn open_telemetry(&self) -> Telemetry {
+        if self.telemetry {
+            Telemetry::open(Path::new(TELEMETRY_FILE))
+        } else {
+            Telemetry::disabled()
+        }
+    }
 }
 
+/// First wait before restarting a panicked governor, doubling per panic up
+/// to the max; a run that lasted the max starts over from the first
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+const USAGE: &str = "\
+usage: thermal-governor [options]
+       thermal-governor simulate [options]   (see simulate --help)
+       thermal-governor replay [options] TELEMETRY.jsonl...
+       thermal-governor print-config [--config FILE]
+       thermal-governor restore-settings [--snapshot FILE]
+  --config FILE        main config file (default /etc/thermal-governor/config.toml)
+  --dry-run            read sensors but never write sysfs or saved state
+  --sysfs-root DIR     resolve all sysfs paths under DIR
+  --fake-temp C        use an in-memory fake backend fixed at C °C
+  --profile NAME       power-saver | balanced | performance
+  --telemetry          record every poll to /var/lib/thermal-governor/telemetry.jsonl";
+
 // =============================================================================
 // Main
 // =============================================================================
 
 fn main() {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let subcommand = match argv.first().map(String::as_str) {
+        Some("simulate") => Some(sim::run as fn(&[String]) -> Result<(), String>),
+        Some("replay") => Some(replay::run as fn(&[String]) -> Result<(), String>),
+        Some("print-config") => Some(config::print as fn(&[String]) -> Result<(), String>),
+        Some("restore-settings") => Some(hw::restore_saved as fn(&[String]) -> Result<(), String>),
+        _ => None,
+    };
+    if let Some(run) = subcommand {
+        if let Err(e) = run(&argv[1..]) {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+        return;
+    }
+
+    let args = Args::parse(&argv).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    let probed = args.freq_bounds();
+    let bounds = probed.clone().unwrap_or_else(FreqBounds::reference);
+    let mut cfg = Config::load(&args.config, &bounds).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {e}");
+        std::process::exit(2);
+    });
+
     eprintln!("================================================");
     eprintln!("  thermal-governor v{}", env!("CARGO_PKG_VERSION"));
     eprintln!("  Auto-tuning thermal manager for ThinkPad X1");
     eprintln!("================================================");
-    eprintln!("  Power Saver  │ EPP=power          │ fanless (<58°C)");
-    eprintln!("  Balanced     │ EPP=balance_power   │ moderate (<80°C)");
-    eprintln!("  Performance  │ EPP=performance     │ max sustained (<95°C)");
+    eprintln!("  Power Saver  │ EPP={:<15}│ fanless (<58°C)", cfg.power_saver.epp);
+    eprintln!("  Balanced     │ EPP={:<15}│ moderate (<80°C)", cfg.balanced.epp);
+    eprintln!("  Performance  │ EPP={:<15}│ max sustained (<95°C)", cfg.performance.epp);
     eprintln!("────────────────────────────────────────────────");
-    eprintln!("  Tune: every {}s  Persist: every {}s",
-        TUNE_INTERVAL.as_secs(), PERSIST_INTERVAL.as_secs());
+    eprintln!("  Poll: {}s ({}–{}s)  Tune: every {}s  Persist: every {}s",
+        cfg.timing.poll_interval, cfg.timing.min_poll_interval, cfg.timing.max_poll_interval,
+        cfg.timing.tune_interval, cfg.timing.persist_interval);
+    eprintln!("  CPU: {} ({})", bounds.describe(),
+        if probed.is_some() { "from cpufreq" } else { "reference, cpufreq not readable" });
+    eprintln!("  Config: {}", args.config.display());
     eprintln!("  State: {STATE_FILE}");
     eprintln!("================================================\n");
 
-    let mut state = State::load();
+    let mut state = State::load(&cfg);
+    state.read_only = args.read_only();
+    let mut hw = args.backend();
+    let mut telemetry = args.open_telemetry();
+
+    // Whatever the knobs were before we touch them, restored on the way out
+    let original = hw::take_snapshot(hw.as_mut(), args.snapshot_file());
+    let writes_sysfs = !args.dry_run && args.fake_temp.is_none();
+    if writes_sysfs {
+        // Only a panic on the main thread takes the process down. Others
+        // (the governor, the D-Bus watcher) are restarted and must find
+        // their caps still in place.
+        let snap = original.clone();
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            if thread::current().name() != Some("main") {
+                return;
+            }
+            match snap.write_back() {
+                0 => log("main", "Panic: original CPU settings restored"),
+                n => log("main", &format!("Panic: restore failed for {n} settings")),
+            }
+        }));
+    }
 
-    let initial = detect_profile().unwrap_or_else(|| {
+    let initial = args.profile.or_else(dbus::detect_profile).unwrap_or_else(|| {
         log("main", "Cannot detect profile, defaulting to balanced");
         Profile::Balanced
     });
+    let temp = hw.cpu_temp().map_or("?".into(), |t| t.to_string());
     log("main", &format!(
-        "Initial: {} ({}°C, fan {} rpm)", initial.name(), cpu_temp(), fan_rpm(),
+        "Initial: {} ({temp}°C, fan {} rpm)", initial.name(), hw.fan_rpm(),
     ));
 
-    // D-Bus profile change channel
-    let (tx, rx) = mpsc::channel::<Profile>();
-    thread::spawn(move || watch_dbus(tx));
+    // Profile changes from D-Bus (supervised, never closes on its own)
+    let rx = profile_source::spawn();
+
+    // SIGHUP → reload config into the running governor
+    let reload = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&reload))
+        .expect("Failed to register SIGHUP handler");
 
-    // SIGTERM handling
-    let running = Arc::new(AtomicBool::new(true));
-    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&running))
-        .expect("Failed to register SIGTERM handler");
-    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&running))
-        .expect("Failed to register SIGINT handler");
+    // SIGTERM/SIGINT → graceful shutdown with a hard deadline
+    let shutdown = Shutdown::install();
 
-    let dirs = cpufreq_dirs();
     let mut current = initial;
+    let mut source_lost = false;
     let stop = Arc::new(AtomicBool::new(false));
+    let mut backoff = RESTART_BACKOFF;
 
     loop {
         stop.store(false, Ordering::Relaxed);
         let stop_c = Arc::clone(&stop);
+        let returned = Arc::new(AtomicBool::new(false));
+        let returned_c = Arc::clone(&returned);
         let mut state_c = state.clone();
+        let mut cfg_c = cfg.clone();
+        let (cfg_tx, cfg_rx) = mpsc::channel::<Config>();
         let profile = current;
+        let alarm = Alarm::new();
+        let waker = alarm.waker();
 
         let handle = thread::spawn(move || {
-            governor(profile, &mut state_c, &stop_c);
-            state_c
+            let clock = SystemClock::new();
+            let drv = Driver { clock: &clock, reload: &cfg_rx, stop: &stop_c, alarm: &alarm };
+            governor(profile, &mut cfg_c, &mut state_c, hw.as_mut(), &mut telemetry, &drv);
+            returned_c.store(true, Ordering::Relaxed);
+            (state_c, hw, telemetry, cfg_c)
         });
+        let started = Instant::now();
+        let mut died: Option<Instant> = None;
 
-        // Wait for profile switch or shutdown
+        // Wait for profile switch or shutdown. A governor that returned on
+        // its own (no cpufreq) stays down; one that panicked is restarted,
+        // backing off while it keeps doing so.
         let new_profile = loop {
+            if died.is_none() && handle.is_finished() && !returned.load(Ordering::Relaxed) {
+                if started.elapsed() >= RESTART_BACKOFF_MAX {
+                    backoff = RESTART_BACKOFF;
+                }
+                log("main", &format!("Governor thread panicked, restarting in {}s", backoff.as_secs()));
+                died = Some(Instant::now());
+            }
+            if died.is_some_and(|t| t.elapsed() >= backoff) {
+                backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+                break Some(current);
+            }
+            if shutdown.requested() {
+                log("main", "Shutting down");
+                shutdown.arm_deadline(writes_sysfs.then(|| original.clone()));
+                break None;
+            }
+            if reload.swap(false, Ordering::Relaxed) {
+                match Config::load(&args.config, &bounds) {
+                    Ok(new) if new == cfg => log("main", "Reload: configuration unchanged"),
+                    Ok(new) => {
+                        let _ = cfg_tx.send(new.clone());
+                        cfg = new;
+                    }
+                    Err(e) => log("main", &format!("Reload failed, keeping current config: {e}")),
+                }
+            }
             match rx.recv_timeout(Duration::from_secs(1)) {
                 Ok(p) if p != current => break Some(p),
                 Ok(_) => {} // same profile, ignore
-                Err(mpsc::RecvTimeoutError::Timeout) => {
-                    if !running.load(Ordering::Relaxed) {
-                        break None; // shutdown
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    // Only if the supervisor itself died: stay in this profile
+                    if !source_lost {
+                        log("main", &format!("Profile source lost, staying in {}", current.name()));
+                        source_lost = true;
                     }
+                    thread::sleep(Duration::from_secs(1));
                 }
-                Err(mpsc::RecvTimeoutError::Disconnected) => break None,
             }
         };
 
-        // Stop governor
+        // Stop governor: raised first, so a poke it drops as stale still counts
         stop.store(true, Ordering::Relaxed);
-        if let Ok(s) = handle.join() {
-            state = s;
-        }
+        let _ = waker.send(());
+        (hw, telemetry) = match handle.join() {
+            Ok((s, h, t, ran)) => {
+                state = s;
+                // A reload that landed after the governor's last poll
+                if ran != cfg {
+                    state.reconfigure(&ran, &cfg);
+                }
+                (h, t)
+            }
+            Err(_) => {
+                log("main", "Governor thread panicked, reopening hardware");
+                (args.backend(), args.open_telemetry())
+            }
+        };
 
         match new_profile {
             Some(p) => {
@@ -711,14 +1950,306 @@ fn main() {
                 // loop continues → restarts governor with new profile
             }
             None => {
-                // Shutdown: save and reset
-                log("main", "Shutting down");
+                // Shutdown: save and restore
                 state.save();
-                set_max_freq(&dirs, MAX_CAP);
-                apply_base(&dirs, 400_000, "balance_power", 0);
-                log("main", "Reset to defaults. Goodbye.");
+                hw.restore(&original);
+                hw::discard_snapshot(args.snapshot_file());
+                log("main", "Original settings restored. Goodbye.");
                 return;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use clock::VirtualClock;
+
+    fn config() -> Config {
+        Config::for_hardware(&FreqBounds::reference())
+    }
+
+    fn state(cfg: &Config) -> State {
+        State { read_only: true, ..State::from_config(cfg) }
+    }
+
+    /// Run the governor on `hw` for `secs` of virtual time
+    fn drive(profile: Profile, cfg: &mut Config, state: &mut State, hw: &mut FakeBackend, secs: u64) {
+        set_quiet(true);
+        let stop = Arc::new(AtomicBool::new(false));
+        let clock = VirtualClock::new(Duration::from_secs(secs), Arc::clone(&stop));
+        let (_tx, reload) = mpsc::channel();
+        let alarm = Alarm::new();
+        let drv = Driver { clock: &clock, reload: &reload, stop: &stop, alarm: &alarm };
+        governor(profile, cfg, state, hw, &mut Telemetry::disabled(), &drv);
+    }
+
+    /// A tuning window of `secs` at a steady temperature and load
+    fn window(temp: i32, fan_rpm: u32, util: f64, cap: u64, lowest: u64, secs: f64) -> TuneStats {
+        let mut stats = TuneStats::default();
+        let polls = (secs / 2.0) as u32;
+        for _ in 0..polls {
+            stats.record(temp, f64::from(temp), fan_rpm, cap, lowest, 2.0);
+            stats.record_load(Some(util), None, 2.0);
+            stats.record_idle_temp(Some(util), f64::from(temp), 2.0);
+        }
+        stats
+    }
+
+    #[test]
+    fn cool_package_runs_at_max_cap() {
+        let mut cfg = config();
+        let mut state = state(&cfg);
+        let mut hw = FakeBackend::new(40);
+        drive(Profile::Balanced, &mut cfg, &mut state, &mut hw, 60);
+        assert_eq!(hw.max_freq, state.table(Profile::Balanced).max_cap);
+        assert_eq!(hw.freq_writes, 1, "an unchanged cap is not rewritten");
+        assert_eq!(hw.epp, cfg.balanced.epp);
+    }
+
+    #[test]
+    fn hot_package_steps_down_to_its_level() {
+        let mut cfg = config();
+        let mut state = state(&cfg);
+        let mut hw = FakeBackend::new(80);
+        drive(Profile::Balanced, &mut cfg, &mut state, &mut hw, 60);
+        let table = state.table(Profile::Balanced);
+        assert_eq!(hw.max_freq, table.cap_at(80, &cfg.limits));
+        assert!(hw.max_freq < table.max_cap);
+    }
+
+    #[test]
+    fn firmware_throttle_lowers_every_cap() {
+        let cfg = config();
+        let mut state = state(&cfg);
+        let before = state.table(Profile::Balanced).clone();
+        let mut stats = window(70, 0, 1.0, before.max_cap, before.lowest_cap(), 120.0);
+        stats.record_throttle(2);
+        assert_eq!(auto_tune(Profile::Balanced, &stats, &mut state, &cfg), Some("throttled"));
+        let after = state.table(Profile::Balanced);
+        assert!(after.max_cap < before.max_cap);
+        assert!(after.levels.iter().zip(&before.levels).all(|(a, b)| a.cap < b.cap));
+    }
+
+    #[test]
+    fn throttle_moves_the_fan_curve_earlier_within_bounds() {
+        let cfg = config();
+        let mut state = state(&cfg);
+        let t = state.table(Profile::Balanced).clone();
+        let mut stats = window(90, 4000, 1.0, t.max_cap, t.lowest_cap(), 120.0);
+        stats.record_fan_level(4, 120.0);
+        stats.record_throttle(1);
+        state.table_mut(Profile::Balanced).fan_offset = 1 - FAN_OFFSET_MAX;
+        auto_tune(Profile::Balanced, &stats, &mut state, &cfg);
+        assert_eq!(state.table(Profile::Balanced).fan_offset, -FAN_OFFSET_MAX);
+        auto_tune(Profile::Balanced, &stats, &mut state, &cfg);
+        assert_eq!(state.table(Profile::Balanced).fan_offset, -FAN_OFFSET_MAX);
+    }
+
+    #[test]
+    fn pid_throttle_while_swinging_damps_instead_of_calming() {
+        let mut cfg = config();
+        cfg.balanced.controller = ControlMode::Pid;
+        let mut state = state(&cfg);
+        let before = state.table(Profile::Balanced).clone();
+        let mut stats = window(80, 0, 1.0, before.max_cap, before.lowest_cap(), 120.0);
+        for i in 0..20u64 {
+            stats.record_cap_change(3_000_000 + i % 2 * 200_000, 3_200_000 - i % 2 * 200_000);
+        }
+        stats.record_throttle(1);
+        assert_eq!(auto_tune(Profile::Balanced, &stats, &mut state, &cfg), Some("pid_kd_up"));
+        let after = state.table(Profile::Balanced);
+        assert!(after.pid.kd > before.pid.kd);
+        assert_eq!((after.pid.kp, after.pid.ki), (before.pid.kp, before.pid.ki));
+        assert!(after.max_cap < before.max_cap);
+        assert_eq!(after.levels, before.levels, "the levels don't drive PID");
+    }
+
+    #[test]
+    fn performance_danger_follows_the_tables_hot_threshold() {
+        let cfg = config();
+        let mut state = state(&cfg);
+        for lv in &mut state.table_mut(Profile::Performance).levels {
+            lv.above -= 5;
+        }
+        let t = state.table(Profile::Performance).clone();
+        let stats = window(t.hot_threshold() + 1, 0, 1.0, t.max_cap, t.lowest_cap(), 120.0);
+        assert_eq!(auto_tune(Profile::Performance, &stats, &mut state, &cfg), Some("danger"));
+    }
+
+    #[test]
+    fn headroom_under_load_raises_top_caps() {
+        let cfg = config();
+        let mut state = state(&cfg);
+        let before = state.table(Profile::Balanced).clone();
+        let stats = window(55, 0, 0.9, before.max_cap, before.lowest_cap(), 120.0);
+        assert_eq!(auto_tune(Profile::Balanced, &stats, &mut state, &cfg), Some("raise_top"));
+        assert!(state.table(Profile::Balanced).max_cap > before.max_cap);
+    }
+
+    #[test]
+    fn cool_idle_window_leaves_caps_alone() {
+        let cfg = config();
+        let mut state = state(&cfg);
+        let before = state.table(Profile::Balanced).clone();
+        let stats = window(45, 0, 0.05, before.max_cap, before.lowest_cap(), 120.0);
+        auto_tune(Profile::Balanced, &stats, &mut state, &cfg);
+        assert_eq!(state.table(Profile::Balanced).levels, before.levels);
+        assert_eq!(state.table(Profile::Balanced).max_cap, before.max_cap);
+    }
+
+    #[test]
+    fn stored_table_needs_a_level_and_known_keys() {
+        let table = |json: &str| serde_json::from_str::<ThermalTable>(json).map(|_| ());
+        assert!(table(r#"{"max_cap": 4000000, "thresholds": [], "caps": []}"#).is_err());
+        assert!(table(r#"{"max_cap": 4000000, "levels": []}"#).is_err());
+        assert!(table(r#"{"max_cap": 4000000, "thresholds": [70], "caps": [3000000], "tresholds": [80]}"#).is_err());
+        assert!(table(r#"{"max_cap": 4000000, "thresholds": [70], "caps": [3000000], "hysteresis": 2}"#).is_ok());
+    }
+
+    #[test]
+    fn burst_baseline_follows_the_idle_temperature() {
+        let cfg = config();
+        let mut state = state(&cfg);
+        let t = state.table(Profile::PowerSaver);
+        let stats = window(52, 0, 0.05, t.max_cap, t.lowest_cap(), 120.0);
+        auto_tune(Profile::PowerSaver, &stats, &mut state, &cfg);
+        assert_eq!(state.table(Profile::PowerSaver).burst.map(|b| b.baseline), Some(52 + burst::IDLE_MARGIN));
+    }
+
+    #[test]
+    fn poll_backs_off_only_when_nothing_can_sneak_up() {
+        let t = config().timing;
+        let thresholds = [70, 80, 90];
+        let at = |temp: i32, rate: f64, util: f64| Reading {
+            temp, rate, dt: 2.0, util: Some(util), pressure: None, fan_on: false,
+        };
+        let backed = (4.0 * t.poll_interval).min(t.max_poll_interval);
+        let poll = |setpoint, r: &Reading, settled, uncapped| {
+            next_poll(&t, &thresholds, setpoint, r, settled, uncapped, 2.0 * t.poll_interval)
+        };
+        // Table law: cold, idle and settled backs off; near a threshold,
+        // past them all or moving fast polls fastest
+        assert_eq!(poll(None, &at(45, 0.0, 0.05), true, true), backed);
+        assert_eq!(poll(None, &at(45, 0.0, 0.9), true, true), t.poll_interval);
+        assert_eq!(poll(None, &at(45, 0.0, 0.05), false, true), t.poll_interval);
+        assert_eq!(poll(None, &at(68, 0.0, 0.9), true, true), t.min_poll_interval);
+        assert_eq!(poll(None, &at(95, 0.0, 0.9), true, true), t.min_poll_interval);
+        assert_eq!(poll(None, &at(45, 2.0, 0.05), true, true), t.min_poll_interval);
+        // Setpoint law: uncapped never backs off, past the setpoint is fast
+        assert_eq!(poll(Some(78), &at(45, 0.0, 0.05), true, true), t.poll_interval);
+        assert_eq!(poll(Some(78), &at(45, 0.0, 0.05), true, false), backed);
+        assert_eq!(poll(Some(78), &at(75, 0.0, 0.9), true, false), t.poll_interval);
+        assert_eq!(poll(Some(78), &at(82, 0.0, 0.9), true, false), t.min_poll_interval);
+    }
+
+    #[test]
+    fn short_window_is_not_tuned() {
+        let cfg = config();
+        let mut state = state(&cfg);
+        let stats = window(99, 5000, 1.0, 4_000_000, 2_000_000, 10.0);
+        assert_eq!(auto_tune(Profile::Performance, &stats, &mut state, &cfg), None);
+    }
+
+    #[test]
+    fn interp_walks_any_ladder_length() {
+        for (ladder, pos, want) in [
+            (&[][..], 0.5, 0.0),
+            (&[3.0][..], 0.7, 3.0),
+            (&[10.0, 20.0][..], 0.5, 15.0),
+            (&[10.0, 20.0, 40.0][..], 0.75, 30.0),
+            (&[10.0, 20.0, 40.0][..], -1.0, 10.0),
+            (&[10.0, 20.0, 40.0][..], 2.0, 40.0),
+        ] {
+            assert_eq!(interp(ladder, pos), want, "{ladder:?} at {pos}");
+        }
+    }
+
+    #[test]
+    fn other_cores_lag_the_p_cores_and_meet_at_the_bottom() {
+        let mut t = ThermalTable::balanced();
+        let p = t.all_levels();
+        assert_eq!(t.ladder(CoreType::Performance), p);
+        assert_eq!(t.ladder(CoreType::Efficient), [p[0], p[0], p[1], p[2], p[4]]);
+        assert_eq!(t.ladder(CoreType::LowPower), [p[0], p[0], p[0], p[1], p[4]]);
+
+        let limits = config().limits;
+        for (core, cap, want) in [
+            (CoreType::Performance, 2_750_000, 2_750_000),
+            (CoreType::Efficient, 4_000_000, 4_000_000),
+            (CoreType::Efficient, 3_500_000, 4_000_000),
+            (CoreType::Efficient, 3_000_000, 3_500_000),
+            (CoreType::Efficient, 2_750_000, 3_200_000), // 3.25 GHz, on the 100 MHz step
+            (CoreType::LowPower, 2_750_000, 3_700_000),
+            (CoreType::LowPower, 2_000_000, 2_000_000),
+            (CoreType::LowPower, 1_500_000, 2_000_000),
+        ] {
+            assert_eq!(t.core_cap(core, cap, &limits), want, "{core:?} at {cap}");
+        }
+
+        // A configured ladder replaces the lag, spread over the same position
+        t.ladders.insert(CoreType::Efficient, vec![3_000_000, 1_000_000]);
+        assert_eq!(t.ladder(CoreType::Efficient), [3_000_000, 1_000_000]);
+        assert_eq!(t.core_cap(CoreType::Efficient, 3_000_000, &limits), 2_000_000);
+    }
+
+    #[test]
+    fn power_limits_follow_the_cap_under_the_sustainable_power() {
+        let mut t = ThermalTable::balanced();
+        let power = config().balanced.power;
+        let mut low_pl2 = power.clone();
+        low_pl2.pl2 = vec![20.0; 5];
+        for (sustainable, power, cap, want) in [
+            (None, &power, 4_000_000, (28.0, 50.0)),
+            (None, &power, 3_000_000, (18.0, 30.0)),
+            (None, &power, 2_750_000, (16.0, 25.0)),
+            (None, &power, 1_500_000, (10.0, 12.0)),
+            (Some(20.0), &power, 4_000_000, (20.0, 50.0)),
+            (Some(20.0), &power, 3_000_000, (18.0, 30.0)),
+            (None, &low_pl2, 4_000_000, (28.0, 28.0)),
+        ] {
+            t.sustainable_w = sustainable;
+            assert_eq!(t.power_limits(power, cap), want, "{sustainable:?} at {cap}");
+        }
+    }
+
+    #[test]
+    fn sustainable_power_drops_when_hot_and_creeps_up_when_cool() {
+        let cfg = config();
+        let mut state = state(&cfg);
+        let t = state.table(Profile::Balanced);
+        let (max_cap, lowest) = (t.max_cap, t.lowest_cap());
+        // One window after another: (max °C, avg W, learned W)
+        for (temp, watts, want) in [
+            (55, 22.0, None),        // nothing learned until it first runs hot
+            (80, 25.0, Some(23.8)),  // hot: 95 % of what it drew
+            (80, 30.0, Some(23.8)),  // hotter draw never raises it
+            (55, 22.0, Some(24.8)),  // cool and pressing on the limit: +1 W
+            (55, 12.0, Some(24.8)),  // cool but well under it: unchanged
+            (80, 5.0, Some(10.0)),   // never below the lowest PL1
+        ] {
+            let mut stats = window(temp, 0, 0.9, max_cap, lowest, 120.0);
+            stats.record_power(watts, 120.0);
+            auto_tune(Profile::Balanced, &stats, &mut state, &cfg);
+            assert_eq!(state.table(Profile::Balanced).sustainable_w, want, "{watts} W at {temp}°C");
+        }
+    }
+
+    #[test]
+    fn counter_rate_needs_two_ordered_reads() {
+        let at = |v: u64, secs: u64| Some((v, Duration::from_secs(secs)));
+        for (before, after, want) in [
+            (at(100, 1), at(300, 3), Some(100.0)),
+            (at(100, 1), at(100, 3), Some(0.0)),
+            (None, at(300, 3), None),
+            (at(100, 1), None, None),
+            (at(300, 1), at(100, 3), None), // wrapped
+            (at(100, 3), at(300, 3), None),
+        ] {
+            assert_eq!(per_sec(before, after), want, "{before:?} → {after:?}");
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+
+// =============================================================================
+// Learned thermal model: first-order RC with ambient offset, fitted online
+// =============================================================================
+
+/// Package power grows roughly with f^2.5 across the DVFS range
+const HEAT_EXP: f64 = 2.5;
+
+/// Forgetting factor: older polls fade out over ~500 polls (17 min at 2s),
+/// so the fit follows dust, fan wear and the seasons
+const FORGET: f64 = 0.998;
+
+/// Covariance trace above which forgetting pauses. At idle nothing excites
+/// the model and the covariance would otherwise grow without bound.
+const MAX_TRACE: f64 = 1e4;
+
+/// Polls before predictions are trusted over the table
+const MIN_SAMPLES: u64 = 150;
+
+/// Heat input for the fit: utilization × cap^HEAT_EXP, cap in GHz
+pub fn heat(util: f64, cap: u64) -> f64 {
+    util.clamp(0.0, 1.0) * (cap as f64 / 1e6).powf(HEAT_EXP)
+}
+
+/// dT/dt = (ambient − T) / τ + gain · heat, kept as the linear-regression
+/// coefficients `[ambient/τ, −1/τ, gain]` with their RLS covariance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThermalModel {
+    coef: [f64; 3],
+    cov: [[f64; 3]; 3],
+    samples: u64,
+    /// Mean absolute one-poll prediction error, °C
+    error: f64,
+}
+
+impl Default for ThermalModel {
+    /// A guess to start from: τ 60s, ambient 35°C, full load at 3 GHz
+    /// settling 50°C above ambient. The covariance lets data override it fast.
+    fn default() -> Self {
+        let tau = 60.0;
+        Self {
+            coef: [35.0 / tau, -1.0 / tau, 50.0 / tau / heat(1.0, 3_000_000)],
+            cov: [[10.0, 0.0, 0.0], [0.0, 1e-3, 0.0], [0.0, 0.0, 1e-2]],
+            samples: 0,
+            error: 0.0,
+        }
+    }
+}
+
+impl ThermalModel {
+    /// Fit one poll: `temp` went to `next` over `dt` seconds at `heat`.
+    pub fn observe(&mut self, temp: i32, next: i32, dt: f64, heat: f64) {
+        if !(dt > 0.0 && heat.is_finite()) {
+            return;
+        }
+        let x = [1.0, f64::from(temp), heat];
+        let y = f64::from(next - temp) / dt;
+
+        let predicted = self.predict(temp, heat, dt);
+        self.error += ((predicted - f64::from(next)).abs() - self.error) * 0.02;
+
+        // Recursive least squares with forgetting
+        let px: [f64; 3] = std::array::from_fn(|i| (0..3).map(|j| self.cov[i][j] * x[j]).sum());
+        let denom = FORGET + (0..3).map(|i| x[i] * px[i]).sum::<f64>();
+        let err = y - (0..3).map(|i| self.coef[i] * x[i]).sum::<f64>();
+        let trace: f64 = (0..3).map(|i| self.cov[i][i]).sum();
+        let forget = if trace > MAX_TRACE { 1.0 } else { FORGET };
+        for i in 0..3 {
+            self.coef[i] += px[i] / denom * err;
+            for j in 0..3 {
+                self.cov[i][j] = (self.cov[i][j] - px[i] * px[j] / denom) / forget;
+            }
+        }
+        self.samples += 1;
+    }
+
+    /// Seconds to settle ~63% of the way; `None` while the fit is unstable
+    pub fn tau(&self) -> Option<f64> {
+        (self.coef[1] < 0.0).then(|| -1.0 / self.coef[1])
+    }
+
+    pub fn ambient(&self) -> Option<f64> {
+        self.tau().map(|tau| self.coef[0] * tau)
+    }
+
+    /// Enough polls seen and a physically sensible fit
+    pub fn trusted(&self) -> bool {
+        self.samples >= MIN_SAMPLES
+            && self.coef[2] > 0.0
+            && self.tau().is_some_and(|tau| (1.0..=3600.0).contains(&tau))
+    }
+
+    /// Temperature `secs` ahead holding `heat`, from the exact solution of
+    /// the first-order step response.
+    pub fn predict(&self, temp: i32, heat: f64, secs: f64) -> f64 {
+        let [c0, c1, gain] = self.coef;
+        let temp = f64::from(temp);
+        if c1 >= 0.0 {
+            // Not a cooling system yet; extrapolate the slope
+            return temp + (c0 + c1 * temp + gain * heat) * secs;
+        }
+        let settled = -(c0 + gain * heat) / c1;
+        settled + (temp - settled) * (c1 * secs).exp()
+    }
+
+    pub fn describe(&self) -> String {
+        match (self.tau(), self.ambient()) {
+            (Some(tau), Some(amb)) if self.trusted() => format!(
+                "τ={tau:.0}s ambient={amb:.0}°C ±{:.1}°C over {} polls",
+                self.error, self.samples,
+            ),
+            _ => format!("learning, {}/{MIN_SAMPLES} polls", self.samples.min(MIN_SAMPLES)),
+        }
+    }
+}
+
+/// Polls gathered until they span a nominal poll interval, so the fit
+/// sees steps of the same length however fast the governor polls.
+#[derive(Debug, Default)]
+pub struct FitWindow {
+    /// Starting temperature, seconds covered, ∫ heat dt
+    open: Option<(i32, f64, f64)>,
+}
+
+impl FitWindow {
+    /// Add a poll that went from `prev` to `temp`; returns true when it
+    /// completed a window and the model was fitted on it.
+    pub fn push(&mut self, model: &mut ThermalModel, prev: i32, temp: i32, dt: f64, heat: f64, span: f64) -> bool {
+        let (start, secs, heat_secs) = self.open.get_or_insert((prev, 0.0, 0.0));
+        *secs += dt;
+        *heat_secs += heat * dt;
+        if *secs < span - 1e-6 {
+            return false;
+        }
+        let (start, secs, heat_secs) = (*start, *secs, *heat_secs);
+        self.open = None;
+        model.observe(start, temp, secs, heat_secs / secs);
+        true
+    }
+
+    /// Drop a partial window, e.g. across a poll the model can't learn from
+    pub fn clear(&mut self) {
+        self.open = None;
+    }
+}
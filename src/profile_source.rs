@@ -0,0 +1,124 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{dbus, log, Profile};
+
+// =============================================================================
+// Profile source: supervised D-Bus watcher with a polling fallback
+// =============================================================================
+
+const BACKOFF_MIN: Duration = Duration::from_secs(5);
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
+/// A watcher that stayed up this long was healthy; backoff starts over
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+/// How often `ActiveProfile` is read while the watcher is down
+const POLL_FALLBACK: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Health {
+    /// Subscribed to change signals
+    Watching,
+    /// Watcher down, but `ActiveProfile` can still be read
+    Polling,
+    /// Nothing answers; the current governor keeps running as is
+    Unavailable,
+}
+
+/// Drops repeats so main only hears about actual changes
+struct Forwarder {
+    tx: mpsc::Sender<Profile>,
+    last: Option<Profile>,
+}
+
+impl Forwarder {
+    /// False once main has stopped listening
+    fn send(&mut self, name: &str) -> bool {
+        match Profile::parse(name) {
+            Some(p) if self.last == Some(p) => {}
+            Some(p) => {
+                if self.last.is_some() {
+                    log("profiles", &format!("Profile changed → {name}"));
+                }
+                self.last = Some(p);
+                return self.tx.send(p).is_ok();
+            }
+            None => log("profiles", &format!("Ignoring unknown profile '{name}'")),
+        }
+        true
+    }
+}
+
+/// Start the supervisor thread. The channel stays open for as long as the
+/// receiver does, whatever happens to D-Bus.
+pub fn spawn() -> mpsc::Receiver<Profile> {
+    let (tx, rx) = mpsc::channel();
+    thread::Builder::new()
+        .name("profile-source".into())
+        .spawn(move || supervise(Forwarder { tx, last: None }))
+        .expect("Failed to spawn profile source");
+    rx
+}
+
+fn supervise(mut fwd: Forwarder) {
+    let mut backoff = BACKOFF_MIN;
+    let mut health = Health::Watching;
+
+    loop {
+        let started = Instant::now();
+        let reason = match dbus::Watcher::connect() {
+            Ok(watcher) => {
+                let verb = if health == Health::Watching { "Watching" } else { "Recovered, watching" };
+                log("profiles", &format!("{verb} {}", watcher.name()));
+                health = Health::Watching;
+                // Own thread, so a panic in the watcher is just another failure
+                let run = thread::scope(|s| {
+                    s.spawn(|| watcher.run(&mut |name| fwd.send(&name))).join()
+                });
+                match run {
+                    Ok(Ok(())) => return,
+                    Ok(Err(e)) => e,
+                    Err(_) => "watcher panicked".into(),
+                }
+            }
+            Err(e) => e,
+        };
+        if started.elapsed() >= HEALTHY_RUN {
+            backoff = BACKOFF_MIN;
+        }
+
+        // Degraded until the next attempt: poll so switches are still seen
+        let retry_at = Instant::now() + backoff;
+        loop {
+            let now = match dbus::active_profile() {
+                Ok(name) => {
+                    if !fwd.send(&name) {
+                        return;
+                    }
+                    Health::Polling
+                }
+                Err(_) => Health::Unavailable,
+            };
+            if now != health {
+                health = now;
+                let retry = backoff.as_secs();
+                log("profiles", &match now {
+                    Health::Polling => format!(
+                        "Degraded: watcher down ({reason}), polling ActiveProfile every {}s, retrying in {retry}s",
+                        POLL_FALLBACK.as_secs(),
+                    ),
+                    _ => format!(
+                        "Degraded: power-profiles-daemon unreachable ({reason}), keeping {}, retrying in {retry}s",
+                        fwd.last.map_or("current profile", Profile::name),
+                    ),
+                });
+            }
+            let left = retry_at.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            thread::sleep(left.min(POLL_FALLBACK));
+        }
+        backoff = (backoff * 2).min(BACKOFF_MAX);
+    }
+}
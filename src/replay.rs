@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, CONFIG_FILE};
+use crate::filter::SensorFilter;
+use crate::hw::FreqBounds;
+use crate::model::{heat, FitWindow};
+use crate::telemetry::Record;
+use crate::{auto_tune, fmt_secs, freq_ghz, set_quiet, Controller, Profile, Reading, State, TuneStats};
+
+// =============================================================================
+// `replay` subcommand: re-run the controller over recorded telemetry
+// =============================================================================
+
+/// A gap this long between polls means the daemon was restarted
+const RESTART_GAP: f64 = 60.0;
+
+const REPLAY_USAGE: &str = "\
+usage: thermal-governor replay [options] TELEMETRY.jsonl...
+  --config FILE       config.toml to take limits and default tables from
+  --state FILE        candidate tuned-params.json (default: config tables)
+  --no-tune           keep the candidate tables fixed (skip auto_tune)
+  --limit N           differing polls to print (default 20)
+  --out FILE          write the candidate state after replay
+  --verbose           print tuner logs
+
+Temperatures are replayed as recorded: the trace does not react to the
+candidate's caps, so treat results as a comparison of decisions, not of
+resulting temperatures.";
+
+struct ReplayArgs {
+    files: Vec<String>,
+    config: PathBuf,
+    state: Option<String>,
+    tune: bool,
+    limit: usize,
+    out: Option<String>,
+    verbose: bool,
+}
+
+impl ReplayArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut a = Self {
+            files: Vec::new(),
+            config: PathBuf::from(CONFIG_FILE),
+            state: None,
+            tune: true,
+            limit: 20,
+            out: None,
+            verbose: false,
+        };
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            let mut value = |name: &str| it.next().cloned().ok_or(format!("{name} needs a value"));
+            match arg.as_str() {
+                "--config" => a.config = value("--config")?.into(),
+                "--state" => a.state = Some(value("--state")?),
+                "--no-tune" => a.tune = false,
+                "--limit" => {
+                    let v = value("--limit")?;
+                    a.limit = v.parse().map_err(|_| format!("bad limit: {v}"))?;
+                }
+                "--out" => a.out = Some(value("--out")?),
+                "--verbose" => a.verbose = true,
+                "-h" | "--help" => return Err(REPLAY_USAGE.into()),
+                s if s.starts_with("--") => return Err(format!("unknown argument: {s}\n{REPLAY_USAGE}")),
+                _ => a.files.push(arg.clone()),
+            }
+        }
+        if a.files.is_empty() {
+            return Err(REPLAY_USAGE.into());
+        }
+        Ok(a)
+    }
+}
+
+#[derive(Default)]
+struct ProfileSummary {
+    polls: u64,
+    differ: u64,
+    actual_cap_sum: u64,
+    cand_cap_sum: u64,
+    actual_changes: u64,
+    cand_changes: u64,
+    tunes: u64,
+    tunes_differ: u64,
+    throttle_events: u64,
+}
+
+/// Candidate controller for the profile segment being replayed
+struct Segment {
+    profile: Profile,
+    ctl: Controller,
+    filter: SensorFilter,
+    fit: FitWindow,
+    stats: TuneStats,
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let a = ReplayArgs::parse(args)?;
+
+    let cfg = Config::load(&a.config, &FreqBounds::probe(Path::new("/")).unwrap_or_else(FreqBounds::reference))?;
+    let mut state = match &a.state {
+        Some(path) => {
+            let data = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+            serde_json::from_str(&data).map_err(|e| format!("{path}: {e}"))?
+        }
+        None => State::from_config(&cfg),
+    };
+    state.read_only = true;
+
+    set_quiet(!a.verbose);
+    let report = replay(&a, &cfg, &mut state);
+    set_quiet(false);
+    print!("{}", report?);
+
+    if let Some(path) = &a.out {
+        let json = serde_json::to_string_pretty(&state).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("{path}: {e}"))?;
+        println!("Candidate state written to {path}");
+    }
+    Ok(())
+}
+
+/// Replay `a.files` against the candidate `state`, tuning it along the
+/// way; returns the report
+fn replay(a: &ReplayArgs, cfg: &Config, state: &mut State) -> Result<String, String> {
+    let mut out = String::new();
+    let mut summaries: BTreeMap<&'static str, ProfileSummary> = BTreeMap::new();
+    let mut seg: Option<Segment> = None;
+    let mut first_ts: Option<f64> = None;
+    let mut last_ts = 0.0;
+    let mut bad_lines = 0;
+    let mut shown = 0;
+
+    let _ = writeln!(out, "Differing decisions (actual vs candidate):");
+
+    for path in &a.files {
+        let file = fs::File::open(path).map_err(|e| format!("{path}: {e}"))?;
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            let rec: Record = match serde_json::from_str(&line) {
+                Ok(r) => r,
+                Err(_) => {
+                    bad_lines += 1;
+                    continue;
+                }
+            };
+            match rec {
+                Record::Poll {
+                    ts, profile, temp, temp_delta, fan_rpm, fan_level, cap, new_cap, power_w, util, pressure,
+                    throttle_events, peak_temp, mean_temp, ..
+                } => {
+                    let Some(p) = Profile::parse(&profile) else {
+                        bad_lines += 1;
+                        continue;
+                    };
+                    let start = *first_ts.get_or_insert(ts);
+
+                    // Profile switch or daemon restart → the governor started over
+                    let restart = seg
+                        .as_ref()
+                        .is_none_or(|s| s.profile != p || ts - last_ts > RESTART_GAP);
+                    if restart {
+                        seg = Some(Segment {
+                            profile: p,
+                            ctl: Controller::new(state.table(p).max_cap, cfg.timing.poll_interval),
+                            filter: SensorFilter::new(&cfg.filter, cfg.timing.poll_interval),
+                            fit: FitWindow::default(),
+                            stats: TuneStats::default(),
+                        });
+                    }
+                    let dt = if restart { 0.0 } else { ts - last_ts };
+                    let secs = if dt > 0.0 { dt } else { cfg.timing.poll_interval };
+                    last_ts = ts;
+                    let s = seg.as_mut().expect("segment started above");
+
+                    // The candidate's model learns from the recorded caps
+                    if let (false, Some(u), 0) = (restart, util, throttle_events) {
+                        s.fit.push(&mut state.model, temp - temp_delta, temp, dt, heat(u, cap), cfg.timing.poll_interval);
+                    } else {
+                        s.fit.clear();
+                    }
+                    let table = state.table(p);
+                    let cand_cap = s.ctl.cap;
+                    let peak = peak_temp.map_or(temp, |p| p.max(temp));
+                    let reading = Reading::new(s.filter.push(peak, ts), dt, util, pressure, fan_rpm);
+                    let d = s.ctl.decide(cfg.profile(p), table, &state.model, &cfg.limits, &reading);
+                    s.stats.record(peak, mean_temp.unwrap_or(f64::from(temp)), fan_rpm, cand_cap, table.lowest_cap(), secs);
+                    s.stats.record_setpoint(reading.temp, cfg.profile(p).setpoint, secs);
+                    s.stats.record_cap_change(cand_cap, d.new_cap);
+                    s.stats.record_bursts(s.ctl.credits.take_outcomes());
+                    s.stats.record_load(util, pressure, secs);
+                    s.stats.record_idle_temp(util, mean_temp.unwrap_or(f64::from(temp)), secs);
+                    if let Some(w) = power_w {
+                        s.stats.record_power(w, secs);
+                    }
+                    if let Some(level) = fan_level {
+                        s.stats.record_fan_level(level, secs);
+                    }
+                    s.stats.record_throttle(throttle_events);
+
+                    let sum = summaries.entry(p.name()).or_default();
+                    sum.polls += 1;
+                    sum.throttle_events += u64::from(throttle_events);
+                    sum.actual_cap_sum += new_cap;
+                    sum.cand_cap_sum += d.new_cap;
+                    sum.actual_changes += u64::from(new_cap != cap);
+                    sum.cand_changes += u64::from(d.new_cap != cand_cap);
+                    if d.new_cap != new_cap {
+                        sum.differ += 1;
+                        if shown < a.limit {
+                            shown += 1;
+                            let _ = writeln!(
+                                out,
+                                "  +{} {:<12} {temp:>3}°C ({temp_delta:+})  actual {}→{}  candidate {}→{}",
+                                fmt_secs(ts - start), p.name(),
+                                freq_ghz(cap), freq_ghz(new_cap),
+                                freq_ghz(cand_cap), freq_ghz(d.new_cap),
+                            );
+                        }
+                    }
+                }
+                Record::Tune { profile, action, .. } => {
+                    // Tune at the same points the daemon did, over the same polls
+                    let Some(s) = seg.as_mut().filter(|s| s.profile.name() == profile) else {
+                        continue;
+                    };
+                    let cand_action = if a.tune { auto_tune(s.profile, &s.stats, state, cfg) } else { None };
+                    s.stats = TuneStats::default();
+                    let sum = summaries.entry(s.profile.name()).or_default();
+                    sum.tunes += 1;
+                    if a.tune && cand_action != action.as_deref() {
+                        sum.tunes_differ += 1;
+                        if shown < a.limit {
+                            shown += 1;
+                            let _ = writeln!(
+                                out,
+                                "  tune {:<12} actual {}  candidate {}",
+                                profile,
+                                action.as_deref().unwrap_or("none"),
+                                cand_action.unwrap_or("none"),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if shown == 0 {
+        let _ = writeln!(out, "  (none)");
+    }
+    let total: u64 = summaries.values().map(|s| s.polls).sum();
+    let span = first_ts.map_or(0.0, |f| last_ts - f);
+    let _ = writeln!(out, "\nReplayed {total} polls over {} from {} file(s){}",
+        fmt_secs(span), a.files.len(),
+        if bad_lines > 0 { format!(", {bad_lines} unreadable lines skipped") } else { String::new() });
+
+    for (name, s) in &summaries {
+        if s.polls == 0 {
+            continue;
+        }
+        let _ = writeln!(out, "  {name}: {} polls, {} differ ({:.1}%)",
+            s.polls, s.differ, s.differ as f64 * 100.0 / s.polls as f64);
+        let _ = writeln!(out, "    mean cap:    actual {} GHz  candidate {} GHz",
+            freq_ghz(s.actual_cap_sum / s.polls), freq_ghz(s.cand_cap_sum / s.polls));
+        let _ = writeln!(out, "    cap changes: actual {}  candidate {}", s.actual_changes, s.cand_changes);
+        if s.throttle_events > 0 {
+            let _ = writeln!(out, "    firmware throttle events (actual): {}", s.throttle_events);
+        }
+        if a.tune && s.tunes > 0 {
+            let _ = writeln!(out, "    tune decisions: {} ({} differ)", s.tunes, s.tunes_differ);
+        }
+        if let Some(p) = Profile::parse(name) {
+            let t = state.table(p);
+            let _ = writeln!(out, "    candidate table: caps={} thresh={}", t.caps_str(), t.thresholds_str());
+        }
+    }
+    let _ = writeln!(out, "  thermal model: {}", state.model.describe());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::Telemetry;
+    use crate::ThermalTable;
+
+    fn poll(ts: f64, temp: i32, temp_delta: i32, cap: u64) -> Record {
+        Record::Poll {
+            ts, profile: "balanced".into(), temp, temp_delta, fan_rpm: 0, fan_level: None,
+            cap, target: cap, cooldown: 0, new_cap: cap, power_w: None, peak_temp: None,
+            min_temp: None, mean_temp: None, filtered_temp: None, rate: None, credits: None,
+            util: Some(0.9), pressure: None, throttle_events: 0,
+        }
+    }
+
+    #[test]
+    fn replay_reports_where_the_candidate_differs() {
+        set_quiet(true);
+        let dir = std::env::temp_dir().join(format!("thermal-governor-replay-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("telemetry.jsonl");
+
+        // Recorded: the daemon held 4.0 GHz through a climb to 80°C
+        let mut t = Telemetry::open_unrotated(&path);
+        for (i, temp) in [60, 60, 60, 80, 80, 80].into_iter().enumerate() {
+            t.record(&poll(2.0 * i as f64, temp, if i == 3 { 20 } else { 0 }, 4_000_000));
+        }
+        t.record(&Record::Tune {
+            ts: 12.0, profile: "balanced".into(), samples: 6, avg_temp: 70, max_temp: 80, fan_pct: 0,
+            lowest_pct: 0, avg_power_w: None, avg_util: None, avg_pressure: None, throttle_events: 0,
+            action: Some("lower_top".into()), table: ThermalTable::balanced(),
+        });
+        drop(t);
+        let mut f = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut f, b"{\"type\":\"poll\",\"ts\":\n").unwrap();
+
+        let cfg = Config::for_hardware(&FreqBounds::reference());
+        let mut state = State::from_config(&cfg);
+        let a = ReplayArgs::parse(&[path.display().to_string()]).unwrap();
+        let report = replay(&a, &cfg, &mut state).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The candidate jumps to its lowest cap on the fast climb; the
+        // window is too short for it to tune
+        let expected = "\
+Differing decisions (actual vs candidate):
+  +0h00m06s balanced      80°C (+20)  actual 4.0→4.0  candidate 4.0→2.0
+  +0h00m08s balanced      80°C (+0)  actual 4.0→4.0  candidate 2.0→2.0
+  +0h00m10s balanced      80°C (+0)  actual 4.0→4.0  candidate 2.0→2.0
+  tune balanced     actual lower_top  candidate none
+
+Replayed 6 polls over 0h00m10s from 1 file(s), 1 unreadable lines skipped
+  balanced: 6 polls, 3 differ (50.0%)
+    mean cap:    actual 4.0 GHz  candidate 3.0 GHz
+    cap changes: actual 0  candidate 1
+    tune decisions: 1 (1 differ)
+    candidate table: caps=4.0/3.5/3.0/2.5/2.0 thresh=65/72/78/83°C
+  thermal model: ";
+        assert!(report.starts_with(expected), "{report}");
+    }
+}
@@ -0,0 +1,240 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::log;
+
+// =============================================================================
+// Sensor discovery
+// =============================================================================
+
+/// How often to rescan sysfs for hotplugged sensors even when reads succeed.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+/// Minimum time between rescans triggered by failed reads.
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Known CPU temperature sources, best first.
+/// (kind, name, preferred labels — empty means "first temp*_input")
+const TEMP_SOURCES: &[(SourceKind, &str, &[&str])] = &[
+    (SourceKind::ThermalZone, "x86_pkg_temp", &[]),
+    (SourceKind::Hwmon, "coretemp", &["Package id 0"]),
+    (SourceKind::Hwmon, "k10temp", &["Tdie", "Tctl"]),
+    (SourceKind::Hwmon, "zenpower", &["Tdie", "Tctl"]),
+    (SourceKind::Hwmon, "thinkpad", &["CPU"]),
+    (SourceKind::Hwmon, "dell_smm", &["CPU"]),
+    (SourceKind::ThermalZone, "acpitz", &[]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceKind {
+    ThermalZone,
+    Hwmon,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TempSensor {
+    pub path: PathBuf,
+    /// Human-readable origin, e.g. "coretemp/Package id 0"
+    pub desc: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FanSensor {
+    pub path: PathBuf,
+    pub desc: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sensors {
+    root: PathBuf,
+    temp: Option<TempSensor>,
+    fans: Vec<FanSensor>,
+    last_scan: Instant,
+}
+
+impl Sensors {
+    /// Scan `<root>/sys/class/{thermal,hwmon}` and log what was picked.
+    pub fn discover(root: &Path) -> Self {
+        let mut s = Self {
+            root: root.to_path_buf(),
+            temp: None,
+            fans: Vec::new(),
+            last_scan: Instant::now(),
+        };
+        s.rescan(true);
+        s
+    }
+
+    /// Package temperature in °C, rescanning once if the sensor disappeared.
+    pub fn cpu_temp(&mut self) -> Option<i32> {
+        if let Some(t) = self.read_temp() {
+            self.maybe_rescan();
+            return Some(t);
+        }
+        if self.last_scan.elapsed() >= RETRY_INTERVAL {
+            self.rescan(false);
+            return self.read_temp();
+        }
+        None
+    }
+
+    /// Highest RPM across all discovered fans.
+    pub fn fan_rpm(&mut self) -> u32 {
+        let mut max = 0;
+        let mut missing = false;
+        for f in &self.fans {
+            match read_i64(&f.path) {
+                Some(rpm) => max = max.max(rpm.max(0) as u32),
+                None => missing = true,
+            }
+        }
+        if missing && self.last_scan.elapsed() >= RETRY_INTERVAL {
+            self.rescan(false);
+        }
+        max
+    }
+
+    /// The temperature file in use, for readers on other threads
+    pub fn temp_path(&self) -> Option<&Path> {
+        self.temp.as_ref().map(|t| t.path.as_path())
+    }
+
+    fn read_temp(&self) -> Option<i32> {
+        read_temp(&self.temp.as_ref()?.path)
+    }
+
+    fn maybe_rescan(&mut self) {
+        if self.last_scan.elapsed() >= RESCAN_INTERVAL {
+            self.rescan(false);
+        }
+    }
+
+    fn rescan(&mut self, initial: bool) {
+        self.last_scan = Instant::now();
+        let temp = find_temp(&self.root);
+        let fans = find_fans(&self.root);
+
+        if initial || temp != self.temp {
+            match &temp {
+                Some(t) => log("sensors", &format!("Temp: {} ({})", t.desc, t.path.display())),
+                None => log("sensors", "No known CPU temperature sensor found!"),
+            }
+        }
+        if initial || fans != self.fans {
+            if fans.is_empty() {
+                log("sensors", "No fan sensors found");
+            } else {
+                let names: Vec<&str> = fans.iter().map(|f| f.desc.as_str()).collect();
+                log("sensors", &format!("Fans: {}", names.join(", ")));
+            }
+        }
+
+        self.temp = temp;
+        self.fans = fans;
+    }
+}
+
+/// °C from a millidegree sysfs file
+pub fn read_temp(path: &Path) -> Option<i32> {
+    read_i64(path).map(|m| (m / 1000) as i32)
+}
+
+fn read_i64(path: &Path) -> Option<i64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    Some(fs::read_to_string(path).ok()?.trim().to_string())
+}
+
+/// Sorted `<dir>/<prefix>N` entries, numerically by N.
+pub(crate) fn numbered_entries(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+    let mut entries: Vec<(u32, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name();
+            let n = name.to_str()?.strip_prefix(prefix)?.parse().ok()?;
+            Some((n, e.path()))
+        })
+        .collect();
+    entries.sort();
+    entries.into_iter().map(|(_, p)| p).collect()
+}
+
+/// `tempN_input`/`fanN_input` files in a hwmon dir, sorted by N.
+fn hwmon_inputs(dir: &Path, kind: &str) -> Vec<(u32, PathBuf)> {
+    let mut inputs: Vec<(u32, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name();
+            let n = name
+                .to_str()?
+                .strip_prefix(kind)?
+                .strip_suffix("_input")?
+                .parse()
+                .ok()?;
+            Some((n, e.path()))
+        })
+        .collect();
+    inputs.sort();
+    inputs
+}
+
+fn find_temp(root: &Path) -> Option<TempSensor> {
+    let zones = numbered_entries(&root.join("sys/class/thermal"), "thermal_zone");
+    let hwmons = numbered_entries(&root.join("sys/class/hwmon"), "hwmon");
+
+    for &(kind, name, labels) in TEMP_SOURCES {
+        let found = match kind {
+            SourceKind::ThermalZone => zones
+                .iter()
+                .find(|z| read_trimmed(&z.join("type")).as_deref() == Some(name))
+                .map(|z| TempSensor {
+                    path: z.join("temp"),
+                    desc: format!("{name} ({})", z.file_name().unwrap_or_default().to_string_lossy()),
+                }),
+            SourceKind::Hwmon => hwmons
+                .iter()
+                .filter(|h| read_trimmed(&h.join("name")).as_deref() == Some(name))
+                .find_map(|h| hwmon_temp(h, name, labels)),
+        };
+        if let Some(t) = found {
+            if read_i64(&t.path).is_some() {
+                return Some(t);
+            }
+        }
+    }
+    None
+}
+
+/// Pick the temp input matching the first preferred label, else the first input.
+fn hwmon_temp(dir: &Path, name: &str, labels: &[&str]) -> Option<TempSensor> {
+    let inputs = hwmon_inputs(dir, "temp");
+    let labelled: Vec<(String, &PathBuf)> = inputs
+        .iter()
+        .filter_map(|(n, p)| Some((read_trimmed(&dir.join(format!("temp{n}_label")))?, p)))
+        .collect();
+
+    for want in labels {
+        if let Some((label, path)) = labelled.iter().find(|(l, _)| l == want) {
+            return Some(TempSensor { path: (*path).clone(), desc: format!("{name}/{label}") });
+        }
+    }
+    let (n, path) = inputs.first()?;
+    Some(TempSensor { path: path.clone(), desc: format!("{name}/temp{n}") })
+}
+
+fn find_fans(root: &Path) -> Vec<FanSensor> {
+    let mut fans = Vec::new();
+    for h in numbered_entries(&root.join("sys/class/hwmon"), "hwmon") {
+        let name = read_trimmed(&h.join("name")).unwrap_or_else(|| "?".into());
+        for (n, path) in hwmon_inputs(&h, "fan") {
+            fans.push(FanSensor { path, desc: format!("{name}/fan{n}") });
+        }
+    }
+    fans
+}
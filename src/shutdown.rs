@@ -0,0 +1,58 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use crate::hw::Snapshot;
+use crate::log;
+
+// =============================================================================
+// Shutdown: SIGTERM/SIGINT → stop governor, persist, restore — or else
+// =============================================================================
+
+/// Graceful path budget; the unit's TimeoutStopSec must stay above this
+const DEADLINE: Duration = Duration::from_secs(10);
+
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    /// Register SIGTERM and SIGINT; each sets the flag, which starts clear.
+    pub fn install() -> Self {
+        let requested = Arc::new(AtomicBool::new(false));
+        for sig in [SIGTERM, SIGINT] {
+            signal_hook::flag::register(sig, Arc::clone(&requested))
+                .expect("Failed to register shutdown signal handler");
+        }
+        Self { requested }
+    }
+
+    pub fn requested(&self) -> bool {
+        self.requested.load(Ordering::Relaxed)
+    }
+
+    /// Start the clock on the graceful path. If the process is still alive
+    /// at the deadline (governor stuck in a sysfs write, say), put `original`
+    /// back directly and exit, so systemd never has to SIGKILL us with our
+    /// caps still applied.
+    pub fn arm_deadline(&self, original: Option<Snapshot>) {
+        thread::Builder::new()
+            .name("shutdown-deadline".into())
+            .spawn(move || {
+                thread::sleep(DEADLINE);
+                log("main", &format!("Shutdown took over {}s, forcing exit", DEADLINE.as_secs()));
+                if let Some(snap) = original {
+                    let failed = snap.write_back();
+                    if failed > 0 {
+                        log("main", &format!("Restore failed for {failed} settings"));
+                    }
+                }
+                process::exit(1);
+            })
+            .expect("Failed to spawn shutdown deadline");
+    }
+}
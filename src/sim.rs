@@ -0,0 +1,570 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use crate::clock::{Alarm, Clock, VirtualClock};
+use crate::config::{Config, CONFIG_FILE};
+use crate::fan::MAX_LEVEL;
+use crate::hw::{self, CoreType, FreqBounds, HardwareBackend, Snapshot, SpanAcc, TempSpan, ThrottleCount};
+use crate::telemetry::Telemetry;
+use crate::{fmt_secs, freq_ghz, governor, set_quiet, Driver, Profile, State};
+
+// =============================================================================
+// Thermal plant: two-node RC model (die → heatsink → ambient)
+// =============================================================================
+
+/// Integration step for the plant (the governor polls every 0.25s at the fastest)
+const SIM_STEP: Duration = Duration::from_millis(100);
+
+const IDLE_W: f64 = 2.0; // package power at zero utilization
+const LOAD_W: f64 = 28.0; // extra watts at 100% utilization and 3.0 GHz
+const POWER_EXP: f64 = 2.5; // P ∝ f^2.5 (voltage rises with frequency)
+const C_DIE: f64 = 3.0; // J/K — tiny, so the die spikes within seconds
+const R_DIE: f64 = 0.8; // K/W die → heatsink
+const C_SINK: f64 = 150.0; // J/K — heatsink + chassis
+const R_SINK_FAN: f64 = 1.5; // K/W heatsink → ambient, fans on
+const R_SINK_NOFAN: f64 = 4.5; // K/W heatsink → ambient, fans off
+const FAN_ON: f64 = 55.0; // firmware fan curve is binary on heatsink temp
+const FAN_OFF: f64 = 48.0;
+const FAN_RPM: u32 = 5800;
+const HW_MAX_FREQ: u64 = 4_500_000;
+const THROTTLE_TRIP: f64 = 100.0; // firmware hard throttle
+const THROTTLE_RELEASE: f64 = 90.0;
+const THROTTLE_FREQ: u64 = 400_000;
+
+/// Utilization over time, looped when shorter than the run.
+struct LoadScript {
+    /// (segment length, utilization 0..1)
+    segments: Vec<(Duration, f64)>,
+    period: Duration,
+}
+
+impl LoadScript {
+    /// Named scenario or "secs:util,secs:util,…"
+    fn parse(spec: &str) -> Result<Self, String> {
+        let spec = match spec {
+            "idle" => "60:0.05",
+            "compile" => "600:1.0,60:0.1",
+            "bursty" => "5:1.0,25:0.1",
+            "mixed" => "120:0.1,10:1.0,50:0.2,300:1.0,120:0.05,3:1.0,30:0.1",
+            s => s,
+        };
+        let mut segments = Vec::new();
+        for part in spec.split(',') {
+            let (secs, util) = part
+                .split_once(':')
+                .ok_or(format!("bad load segment '{part}', want secs:util"))?;
+            let secs: f64 = secs.trim().parse().map_err(|_| format!("bad duration '{secs}'"))?;
+            let util: f64 = util.trim().parse().map_err(|_| format!("bad utilization '{util}'"))?;
+            if secs <= 0.0 || !(0.0..=1.0).contains(&util) {
+                return Err(format!("load segment '{part}' out of range"));
+            }
+            segments.push((Duration::from_secs_f64(secs), util));
+        }
+        let period = segments.iter().map(|(d, _)| *d).sum();
+        Ok(Self { segments, period })
+    }
+
+    fn at(&self, t: Duration) -> f64 {
+        let mut t = Duration::from_nanos((t.as_nanos() % self.period.as_nanos()) as u64);
+        for &(d, util) in &self.segments {
+            if t < d {
+                return util;
+            }
+            t -= d;
+        }
+        0.0
+    }
+}
+
+/// Accumulated over the whole run for the summary
+#[derive(Default)]
+struct PlantStats {
+    polls: u32,
+    peak: f64,
+    /// Seconds spent above each of the starting table's thresholds
+    above: Vec<f64>,
+    fan_secs: f64,
+    /// ∫ fan speed (0..1) dt
+    fan_duty: f64,
+    cap_secs: f64, // ∫ cap dt, in kHz·s
+    /// ∫ clock × utilization dt (kHz·s), for the clock work actually ran at
+    busy_freq: f64,
+    energy_j: f64,
+    throttle_events: u32,
+    cap_changes: u32,
+    oscillations: u32,
+    last_dir: i8,
+}
+
+struct Plant {
+    clock: VirtualClock,
+    load: LoadScript,
+    ambient: f64,
+    thresholds: Vec<i32>,
+    die: f64,
+    sink: f64,
+    /// Fan speed 0..1: firmware's on/off, or the level we set
+    fan: f64,
+    /// Level set by the governor, `None` under the firmware curve
+    fan_level: Option<u8>,
+    throttled: bool,
+    cap: u64,
+    /// RAPL long-term limit in watts, if one is set
+    pl1: Option<f64>,
+    /// ∫ utilization dt, and its value at the last `utilization` call
+    busy: f64,
+    busy_mark: (f64, Duration),
+    /// Sensor readings at every integration step, as the sampler thread would
+    span: SpanAcc,
+    last: Duration,
+    stats: PlantStats,
+}
+
+impl Plant {
+    fn new(clock: VirtualClock, load: LoadScript, ambient: f64, thresholds: Vec<i32>) -> Self {
+        let above = vec![0.0; thresholds.len()];
+        Self {
+            clock,
+            load,
+            ambient,
+            thresholds,
+            die: ambient + 10.0,
+            sink: ambient + 8.0,
+            fan: 0.0,
+            fan_level: None,
+            throttled: false,
+            cap: HW_MAX_FREQ,
+            pl1: None,
+            busy: 0.0,
+            busy_mark: (0.0, Duration::ZERO),
+            span: SpanAcc::default(),
+            last: Duration::ZERO,
+            stats: PlantStats { above, ..PlantStats::default() },
+        }
+    }
+
+    /// Integrate from the last update up to the clock's current time.
+    fn advance(&mut self) {
+        let now = self.clock.now();
+        while self.last + SIM_STEP <= now {
+            self.step(SIM_STEP.as_secs_f64());
+            self.last += SIM_STEP;
+        }
+    }
+
+    fn step(&mut self, dt: f64) {
+        let util = self.load.at(self.last);
+        let mut freq = if self.throttled { THROTTLE_FREQ } else { self.cap.min(HW_MAX_FREQ) };
+        if let (Some(pl1), true) = (self.pl1, util > 0.0) {
+            // RAPL trims frequency until package power fits under PL1
+            let budget = (pl1 - IDLE_W).max(0.0) / (util * LOAD_W);
+            freq = freq.min((3_000_000.0 * budget.powf(1.0 / POWER_EXP)) as u64);
+        }
+        let ghz_ratio = freq as f64 / 3_000_000.0;
+        let power = IDLE_W + util * LOAD_W * ghz_ratio.powf(POWER_EXP);
+        self.busy += util * dt;
+        self.stats.busy_freq += freq as f64 * util * dt;
+
+        let r_sink = R_SINK_NOFAN + (R_SINK_FAN - R_SINK_NOFAN) * self.fan;
+        let die_to_sink = (self.die - self.sink) / R_DIE;
+        let sink_to_air = (self.sink - self.ambient) / r_sink;
+        self.die += (power - die_to_sink) / C_DIE * dt;
+        self.sink += (die_to_sink - sink_to_air) / C_SINK * dt;
+
+        if let Some(level) = self.fan_level {
+            self.fan = f64::from(level) / f64::from(MAX_LEVEL);
+        } else if self.sink > FAN_ON {
+            self.fan = 1.0;
+        } else if self.sink < FAN_OFF {
+            self.fan = 0.0;
+        }
+        if !self.throttled && self.die >= THROTTLE_TRIP {
+            self.throttled = true;
+            self.stats.throttle_events += 1;
+        } else if self.throttled && self.die < THROTTLE_RELEASE {
+            self.throttled = false;
+        }
+
+        self.span.add(self.die as i32);
+        let s = &mut self.stats;
+        s.peak = s.peak.max(self.die);
+        for (secs, &th) in s.above.iter_mut().zip(&self.thresholds) {
+            if self.die > th as f64 {
+                *secs += dt;
+            }
+        }
+        if self.fan > 0.0 {
+            s.fan_secs += dt;
+        }
+        s.fan_duty += self.fan * dt;
+        s.cap_secs += self.cap as f64 * dt;
+        s.energy_j += power * dt;
+    }
+}
+
+impl HardwareBackend for Plant {
+    fn cpu_temp(&mut self) -> Option<i32> {
+        self.advance();
+        self.stats.polls += 1;
+        Some(self.die as i32)
+    }
+
+    fn fan_rpm(&mut self) -> u32 {
+        self.advance();
+        (f64::from(FAN_RPM) * self.fan) as u32
+    }
+
+    fn has_cpufreq(&self) -> bool {
+        true
+    }
+
+    fn core_types(&self) -> Vec<CoreType> {
+        vec![CoreType::Performance]
+    }
+
+    fn set_max_freq(&mut self, _core: CoreType, freq: u64) {
+        self.advance();
+        if freq != self.cap {
+            let dir = if freq > self.cap { 1 } else { -1 };
+            let s = &mut self.stats;
+            s.cap_changes += 1;
+            if s.last_dir != 0 && dir != s.last_dir {
+                s.oscillations += 1;
+            }
+            s.last_dir = dir;
+        }
+        self.cap = freq;
+    }
+
+    fn apply_base(&mut self, _min_freq: u64, _epp: &str, _boost: u8) {}
+
+    fn energy_uj(&mut self) -> Option<u64> {
+        self.advance();
+        Some((self.stats.energy_j * 1e6) as u64)
+    }
+
+    fn has_power_limits(&self) -> bool {
+        true
+    }
+
+    fn set_power_limits(&mut self, limits: Option<(u64, u64)>) {
+        self.advance();
+        self.pl1 = limits.map(|(pl1, _)| pl1 as f64 / 1e6);
+    }
+
+    fn utilization(&mut self) -> Option<f64> {
+        self.advance();
+        let (busy, since) = std::mem::replace(&mut self.busy_mark, (self.busy, self.last));
+        let span = (self.last - since).as_secs_f64();
+        (span > 0.0).then(|| (self.busy - busy) / span)
+    }
+
+    /// The load is a utilization, with no run queue to wait in
+    fn cpu_stall_us(&mut self) -> Option<u64> {
+        None
+    }
+
+    fn throttle_count(&mut self) -> Option<ThrottleCount> {
+        self.advance();
+        Some(ThrottleCount { package: u64::from(self.stats.throttle_events), core: 0 })
+    }
+
+    fn temp_span(&mut self) -> Option<TempSpan> {
+        self.advance();
+        self.span.take()
+    }
+
+    /// The sampler thread in virtual time: a reading every sample period,
+    /// ending the sleep on the first one past `wake_above`
+    fn wait(&mut self, clock: &dyn Clock, alarm: &Alarm, d: Duration, wake_above: Option<i32>) {
+        let Some(above) = wake_above else {
+            return alarm.sleep(clock, d);
+        };
+        let period = hw::sample_period(d);
+        let end = clock.now() + d;
+        let mut last = self.die as i32;
+        while clock.now() < end {
+            alarm.sleep(clock, period.min(end - clock.now()));
+            self.advance();
+            let t = self.die as i32;
+            if t > above && last <= above {
+                return;
+            }
+            last = t;
+        }
+    }
+
+    fn has_fan_control(&self) -> bool {
+        true
+    }
+
+    fn set_fan_level(&mut self, level: Option<u8>) {
+        self.advance();
+        self.fan_level = level;
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot::default()
+    }
+
+    fn restore(&mut self, _snap: &Snapshot) {}
+}
+
+// =============================================================================
+// `simulate` subcommand
+// =============================================================================
+
+const SIM_USAGE: &str = "\
+usage: thermal-governor simulate [options]
+  --profile NAME      power-saver | balanced | performance (default balanced)
+  --duration SECS     virtual time to simulate (default 3600)
+  --load SPEC         idle | compile | bursty | mixed | secs:util,... (default mixed)
+  --ambient C         room temperature (default 25)
+  --config FILE       config.toml to take limits and default tables from
+  --state FILE        start from this tuned-params.json instead of config tables
+  --out FILE          write the final tuned state here
+  --telemetry FILE    record every simulated poll as JSONL
+  --verbose           print governor and tuner logs";
+
+struct SimArgs {
+    profile: Profile,
+    duration: Duration,
+    load: String,
+    ambient: f64,
+    config: PathBuf,
+    state: Option<String>,
+    out: Option<String>,
+    telemetry: Option<String>,
+    verbose: bool,
+}
+
+impl SimArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut a = Self {
+            profile: Profile::Balanced,
+            duration: Duration::from_secs(3600),
+            load: "mixed".into(),
+            ambient: 25.0,
+            config: PathBuf::from(CONFIG_FILE),
+            state: None,
+            out: None,
+            telemetry: None,
+            verbose: false,
+        };
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            let mut value = |name: &str| it.next().cloned().ok_or(format!("{name} needs a value"));
+            match arg.as_str() {
+                "--profile" => {
+                    let v = value("--profile")?;
+                    a.profile = Profile::parse(&v).ok_or(format!("unknown profile: {v}"))?;
+                }
+                "--duration" => {
+                    let v = value("--duration")?;
+                    a.duration = Duration::from_secs(v.parse().map_err(|_| format!("bad duration: {v}"))?);
+                }
+                "--load" => a.load = value("--load")?,
+                "--ambient" => {
+                    let v = value("--ambient")?;
+                    a.ambient = v.parse().map_err(|_| format!("bad ambient: {v}"))?;
+                }
+                "--config" => a.config = value("--config")?.into(),
+                "--state" => a.state = Some(value("--state")?),
+                "--out" => a.out = Some(value("--out")?),
+                "--telemetry" => a.telemetry = Some(value("--telemetry")?),
+                "--verbose" => a.verbose = true,
+                "-h" | "--help" => return Err(SIM_USAGE.into()),
+                _ => return Err(format!("unknown argument: {arg}\n{SIM_USAGE}")),
+            }
+        }
+        Ok(a)
+    }
+}
+
+/// Drive the governor against a fresh plant for `duration` of virtual
+/// time, starting from `state`'s table. The plant keeps the run's stats.
+fn simulate(
+    profile: Profile,
+    mut cfg: Config,
+    state: &mut State,
+    load: LoadScript,
+    duration: Duration,
+    ambient: f64,
+    telemetry: &mut Telemetry,
+) -> Plant {
+    let stop = Arc::new(AtomicBool::new(false));
+    let clock = VirtualClock::new(duration, Arc::clone(&stop));
+    let thresholds = state.table(profile).levels.iter().map(|l| l.above).collect();
+    let mut plant = Plant::new(clock.clone(), load, ambient, thresholds);
+    let (_reload_tx, reload) = mpsc::channel();
+    let alarm = Alarm::new();
+    let drv = Driver { clock: &clock, reload: &reload, stop: &stop, alarm: &alarm };
+    governor(profile, &mut cfg, state, &mut plant, telemetry, &drv);
+    plant
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let a = SimArgs::parse(args)?;
+    let load = LoadScript::parse(&a.load)?;
+
+    let cfg = Config::load(&a.config, &FreqBounds::reference())?;
+    let mut state = match &a.state {
+        Some(path) => {
+            let data = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+            serde_json::from_str(&data).map_err(|e| format!("{path}: {e}"))?
+        }
+        None => State::from_config(&cfg),
+    };
+    state.read_only = true;
+    let initial = state.table(a.profile).clone();
+
+    let mut telemetry = match &a.telemetry {
+        Some(path) => Telemetry::open_unrotated(Path::new(path)),
+        None => Telemetry::disabled(),
+    };
+
+    set_quiet(!a.verbose);
+    let plant = simulate(a.profile, cfg, &mut state, load, a.duration, a.ambient, &mut telemetry);
+    set_quiet(false);
+
+    let s = &plant.stats;
+    let total = plant.clock.now().as_secs_f64();
+    let pct = |secs: f64| secs * 100.0 / total;
+    let final_table = state.table(a.profile);
+
+    println!("Simulated {} of {} (load: {}, ambient {}°C)",
+        fmt_secs(total), a.profile.name(), a.load, a.ambient);
+    println!("  polls:         {}", s.polls);
+    println!("  peak temp:     {:.0}°C", s.peak);
+    println!("  fans on:       {:.0}% (mean speed {:.0}%)", pct(s.fan_secs), pct(s.fan_duty));
+    println!("  hard throttle: {} events", s.throttle_events);
+    println!("  mean cap:      {} GHz", freq_ghz((s.cap_secs / total) as u64));
+    if plant.busy > 0.0 {
+        println!("  busy clock:    {} GHz (mean while working)", freq_ghz((s.busy_freq / plant.busy) as u64));
+    }
+    println!("  mean power:    {:.1} W", s.energy_j / total);
+    println!("  cap changes:   {} ({} oscillations)", s.cap_changes, s.oscillations);
+    println!("  time above thresholds (starting table):");
+    for (secs, th) in s.above.iter().zip(initial.levels.iter().map(|l| l.above)) {
+        println!("    >{th:>3}°C  {:>9}  {:>5.1}%", fmt_secs(*secs), pct(*secs));
+    }
+    println!("  start table:   caps={} thresh={}", initial.caps_str(), initial.thresholds_str());
+    println!("  final table:   caps={} thresh={}", final_table.caps_str(), final_table.thresholds_str());
+    println!("  thermal model: {}", state.model.describe());
+
+    if let Some(path) = &a.out {
+        let json = serde_json::to_string_pretty(&state).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("{path}: {e}"))?;
+        println!("  final state written to {path}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ControlMode;
+    use crate::{PidGains, OVERSHOOT};
+
+    /// Closed-loop run of `profile` from the built-in tables, quietly
+    fn run(profile: Profile, cfg: Config, load: &str, secs: u64) -> (Plant, State) {
+        set_quiet(true);
+        let mut state = State::from_config(&cfg);
+        state.read_only = true;
+        let load = LoadScript::parse(load).expect("valid load");
+        let plant = simulate(profile, cfg, &mut state, load, Duration::from_secs(secs), 25.0, &mut Telemetry::disabled());
+        (plant, state)
+    }
+
+    fn config() -> Config {
+        Config::for_hardware(&FreqBounds::reference())
+    }
+
+    /// Direction reversals of the cap per hour of the run
+    fn reversals_per_hour(p: &Plant) -> f64 {
+        f64::from(p.stats.oscillations) * 3600.0 / p.clock.now().as_secs_f64()
+    }
+
+    #[test]
+    fn table_holds_a_compile_below_the_hot_threshold() {
+        let (p, state) = run(Profile::Balanced, config(), "compile", 3600);
+        let hot = state.table(Profile::Balanced).hot_threshold();
+        assert!(p.stats.peak < f64::from(hot), "peak {:.0}°C", p.stats.peak);
+        assert_eq!(p.stats.throttle_events, 0);
+        assert!(reversals_per_hour(&p) < 60.0, "{:.0} reversals/h", reversals_per_hour(&p));
+    }
+
+    fn balanced(controller: ControlMode) -> Config {
+        let mut cfg = config();
+        cfg.balanced.controller = controller;
+        cfg
+    }
+
+    #[test]
+    fn pid_holds_a_compile_at_its_setpoint() {
+        let cfg = balanced(ControlMode::Pid);
+        let setpoint = cfg.balanced.setpoint;
+        let (p, _) = run(Profile::Balanced, cfg, "compile", 3600);
+        assert!(p.stats.peak <= f64::from(setpoint + OVERSHOOT), "peak {:.0}°C", p.stats.peak);
+        assert_eq!(p.stats.throttle_events, 0);
+        assert!(reversals_per_hour(&p) < 60.0, "{:.0} reversals/h", reversals_per_hour(&p));
+    }
+
+    #[test]
+    fn pid_holds_a_performance_compile_short_of_the_trip_point() {
+        let mut cfg = config();
+        cfg.performance.controller = ControlMode::Pid;
+        let setpoint = cfg.performance.setpoint;
+        let (p, _) = run(Profile::Performance, cfg, "compile", 3600);
+        assert!(p.stats.peak <= f64::from(setpoint + OVERSHOOT), "peak {:.0}°C", p.stats.peak);
+        assert_eq!(p.stats.throttle_events, 0);
+        assert!(reversals_per_hour(&p) < 60.0, "{:.0} reversals/h", reversals_per_hour(&p));
+    }
+
+    #[test]
+    fn pid_rides_load_steps_without_throttling_or_hunting() {
+        let (p, state) = run(Profile::Balanced, balanced(ControlMode::Pid), "mixed", 3600);
+        assert_eq!(p.stats.throttle_events, 0, "peak {:.0}°C", p.stats.peak);
+        assert!(reversals_per_hour(&p) < 120.0, "{:.0} reversals/h", reversals_per_hour(&p));
+        // Load-step spikes aren't a gain problem
+        assert_eq!(state.table(Profile::Balanced).pid, PidGains::default());
+    }
+
+    #[test]
+    fn predictive_settles_and_leaves_the_table_alone() {
+        let cfg = balanced(ControlMode::Predictive);
+        let (before, setpoint) = (cfg.balanced.table.clone(), cfg.balanced.setpoint);
+        let (p, state) = run(Profile::Balanced, cfg, "compile", 3600);
+        assert!(state.model.trusted());
+        assert!(p.stats.peak <= f64::from(setpoint + OVERSHOOT), "peak {:.0}°C", p.stats.peak);
+        assert_eq!(p.stats.throttle_events, 0);
+        assert!(reversals_per_hour(&p) < 60.0, "{:.0} reversals/h", reversals_per_hour(&p));
+        assert_eq!(state.table(Profile::Balanced).levels, before.levels);
+    }
+
+    #[test]
+    fn predictive_catches_performance_bursts_before_the_trip_point() {
+        let mut cfg = config();
+        cfg.performance.controller = ControlMode::Predictive;
+        let setpoint = cfg.performance.setpoint;
+        let (p, _) = run(Profile::Performance, cfg, "bursty", 7200);
+        assert!(p.stats.peak <= f64::from(setpoint + OVERSHOOT), "peak {:.0}°C", p.stats.peak);
+        assert_eq!(p.stats.throttle_events, 0);
+    }
+
+    #[test]
+    fn idle_power_saver_stays_off_its_burst_ceiling() {
+        let (p, state) = run(Profile::PowerSaver, config(), "idle", 1800);
+        assert_eq!(p.cap, state.table(Profile::PowerSaver).max_cap);
+    }
+
+    #[test]
+    fn idle_run_keeps_the_top_cap() {
+        let (p, state) = run(Profile::Balanced, config(), "idle", 1800);
+        assert_eq!(p.cap, state.table(Profile::Balanced).max_cap);
+        assert!(p.stats.cap_changes <= 1);
+    }
+}
@@ -0,0 +1,219 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{log, ThermalTable};
+
+// =============================================================================
+// Telemetry: one JSON record per poll / tuner decision, rotated by size
+// =============================================================================
+
+pub const TELEMETRY_FILE: &str = "/var/lib/thermal-governor/telemetry.jsonl";
+
+const MAX_SIZE: u64 = 16 * 1024 * 1024; // rotate at 16 MiB
+const KEEP: u32 = 4; // telemetry.jsonl.1 … .4
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Poll {
+        ts: f64,
+        profile: String,
+        temp: i32,
+        temp_delta: i32,
+        fan_rpm: u32,
+        /// Level written by our fan curve, absent under firmware control
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fan_level: Option<u8>,
+        /// Cap in effect when the poll started
+        cap: u64,
+        /// `target_cap` before cooldown was applied
+        target: u64,
+        /// Polls of cooldown left when the poll started
+        cooldown: u32,
+        new_cap: u64,
+        /// Package power since the previous poll, from RAPL
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        power_w: Option<f64>,
+        /// Sampler aggregates since the previous poll; `temp` is the
+        /// reading at the poll itself
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        peak_temp: Option<i32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_temp: Option<i32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mean_temp: Option<f64>,
+        /// Temperature after the sensor filter; `temp` is the raw reading
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        filtered_temp: Option<f64>,
+        /// °C/s from the filter's regression
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rate: Option<f64>,
+        /// Burst credits left, °C·s
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        credits: Option<f64>,
+        /// CPU busy fraction since the previous poll, from /proc/stat
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        util: Option<f64>,
+        /// Share of that time some task waited for a CPU, from
+        /// /proc/pressure/cpu
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pressure: Option<f64>,
+        /// Firmware throttle events since the previous poll
+        #[serde(default, skip_serializing_if = "is_zero")]
+        throttle_events: u32,
+    },
+    Tune {
+        ts: f64,
+        profile: String,
+        samples: u32,
+        avg_temp: i32,
+        max_temp: i32,
+        fan_pct: u32,
+        lowest_pct: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        avg_power_w: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        avg_util: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        avg_pressure: Option<f64>,
+        #[serde(default)]
+        throttle_events: u32,
+        /// What `auto_tune` changed, `None` if it left the table alone
+        action: Option<String>,
+        table: ThermalTable,
+    },
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+pub struct Telemetry {
+    path: PathBuf,
+    out: Option<BufWriter<File>>,
+    size: u64,
+    max_size: u64,
+}
+
+impl Telemetry {
+    pub fn disabled() -> Self {
+        Self { path: PathBuf::new(), out: None, size: 0, max_size: 0 }
+    }
+
+    /// Append to `path`, rotating once it grows past the size limit.
+    pub fn open(path: &Path) -> Self {
+        Self::open_with_limit(path, MAX_SIZE)
+    }
+
+    /// Single file, never rotated (simulator traces).
+    pub fn open_unrotated(path: &Path) -> Self {
+        Self::open_with_limit(path, u64::MAX)
+    }
+
+    fn open_with_limit(path: &Path, max_size: u64) -> Self {
+        let mut t = Self { path: path.to_path_buf(), out: None, size: 0, max_size };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(f) => {
+                t.size = f.metadata().map(|m| m.len()).unwrap_or(0);
+                t.out = Some(BufWriter::new(f));
+                log("telemetry", &format!("Recording to {}", path.display()));
+            }
+            Err(e) => log("telemetry", &format!("Cannot open {}: {e}", path.display())),
+        }
+        t
+    }
+
+    pub fn record(&mut self, rec: &Record) {
+        let Some(out) = self.out.as_mut() else { return };
+        let mut line = match serde_json::to_string(rec) {
+            Ok(l) => l,
+            Err(e) => {
+                log("telemetry", &format!("Serialize failed: {e}"));
+                return;
+            }
+        };
+        line.push('\n');
+        if let Err(e) = out.write_all(line.as_bytes()).and_then(|()| out.flush()) {
+            log("telemetry", &format!("Write failed ({e}), telemetry disabled"));
+            self.out = None;
+            return;
+        }
+        self.size += line.len() as u64;
+        if self.size >= self.max_size {
+            self.rotate();
+        }
+    }
+
+    fn rotate(&mut self) {
+        self.out = None;
+        let numbered = |n: u32| PathBuf::from(format!("{}.{n}", self.path.display()));
+        for n in (1..KEEP).rev() {
+            let _ = fs::rename(numbered(n), numbered(n + 1));
+        }
+        let _ = fs::rename(&self.path, numbered(1));
+        match File::create(&self.path) {
+            Ok(f) => {
+                self.out = Some(BufWriter::new(f));
+                self.size = 0;
+            }
+            Err(e) => log("telemetry", &format!("Rotate failed ({e}), telemetry disabled")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poll(ts: f64) -> Record {
+        Record::Poll {
+            ts, profile: "balanced".into(), temp: 60, temp_delta: 0, fan_rpm: 0, fan_level: None,
+            cap: 4_000_000, target: 4_000_000, cooldown: 0, new_cap: 4_000_000, power_w: None,
+            peak_temp: None, min_temp: None, mean_temp: None, filtered_temp: None, rate: None,
+            credits: None, util: None, pressure: None, throttle_events: 0,
+        }
+    }
+
+    #[test]
+    fn rotates_at_size_and_keeps_the_newest_files() {
+        crate::set_quiet(true);
+        let dir = std::env::temp_dir().join(format!("thermal-governor-telemetry-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("telemetry.jsonl");
+        let line = serde_json::to_string(&poll(0.0)).unwrap().len() as u64 + 1;
+
+        // Three records to a file
+        let mut t = Telemetry::open_with_limit(&path, 3 * line);
+        for ts in 0..20 {
+            t.record(&poll(f64::from(ts)));
+        }
+        drop(t);
+
+        let read = |p: &Path| -> Vec<f64> {
+            fs::read_to_string(p)
+                .unwrap()
+                .lines()
+                .map(|l| match serde_json::from_str(l).unwrap() {
+                    Record::Poll { ts, .. } => ts,
+                    Record::Tune { .. } => panic!("unexpected tune record"),
+                })
+                .collect()
+        };
+        let numbered = |n: u32| PathBuf::from(format!("{}.{n}", path.display()));
+        assert!(!numbered(KEEP + 1).exists());
+        for n in 1..=KEEP {
+            assert!(fs::metadata(numbered(n)).unwrap().len() >= 3 * line, "{n} rotated short");
+        }
+        // Oldest first, nothing lost or repeated since the oldest kept file
+        let kept: Vec<f64> = (1..=KEEP).rev().map(numbered).chain([path.clone()]).flat_map(|p| read(&p)).collect();
+        assert_eq!(kept, (20 - kept.len() as u32..20).map(f64::from).collect::<Vec<_>>());
+        assert_eq!(read(&path), [18.0, 19.0]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -30,25 +30,32 @@ if systemctl is-enabled --quiet "$SERVICE_NAME" 2>/dev/null; then
     systemctl disable "$SERVICE_NAME"
 fi
 
+# Stopping the service restores the CPU settings it found at startup.
+# Its snapshot is only left behind if that didn't happen: put those exact
+# values back with the binary while it is still installed.
+if [ -f "$STATE_DIR/original-settings.json" ]; then
+    info "Service did not restore CPU settings, restoring saved values..."
+    if [ -x "$BIN_PATH" ]; then
+        "$BIN_PATH" restore-settings --snapshot "$STATE_DIR/original-settings.json" \
+            || echo "    Some settings could not be restored, snapshot kept"
+    else
+        echo "    $BIN_PATH missing, snapshot kept at $STATE_DIR/original-settings.json"
+    fi
+fi
+
 # Remove files
 [ -f "$SERVICE_PATH" ] && info "Removing service file" && rm -f "$SERVICE_PATH"
 [ -f "$BIN_PATH" ]     && info "Removing binary"       && rm -f "$BIN_PATH"
 
 systemctl daemon-reload
 
-# Reset CPU to defaults
-info "Resetting CPU to defaults..."
-for d in /sys/devices/system/cpu/cpu*/cpufreq/; do
-    echo 400000  > "${d}scaling_min_freq"  2>/dev/null
-    echo 4500000 > "${d}scaling_max_freq"  2>/dev/null
-    echo balance_power > "${d}energy_performance_preference" 2>/dev/null
-done
-echo 0 > /sys/devices/system/cpu/intel_pstate/hwp_dynamic_boost 2>/dev/null
-
 echo ""
-info "Uninstalled. CPU reset to defaults."
+info "Uninstalled."
 echo ""
 
+if [ -d "/etc/thermal-governor" ]; then
+    echo "  Configuration kept at: /etc/thermal-governor"
+fi
 if [ -d "$STATE_DIR" ]; then
     echo "  Learned parameters kept at: $STATE_DIR"
     echo "  To remove: sudo rm -rf $STATE_DIR"